@@ -0,0 +1,111 @@
+use raytracer::{
+    aov::Aov,
+    camera::Camera,
+    denoise::Denoiser,
+    hittable::HittableList,
+    material::Surface,
+    shape::{a_box::a_box, sphere::Sphere, Element},
+    texture::Texture,
+    vector::{Color, Point, Vector3},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+};
+
+fn main() -> io::Result<()> {
+    let mut world = HittableList::default();
+
+    let ground = Surface::Diffuse {
+        albedo: Texture::Checkered {
+            even: Color::new(0.2, 0.3, 0.1),
+            odd: Color::from_one(0.9),
+            scale: 0.5,
+        },
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(0., -1000., 0.),
+        1000.,
+        ground,
+    )));
+
+    let diffuse = Surface::Diffuse {
+        albedo: Texture::SolidColor(Color::new(0.4, 0.2, 0.1)),
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(-4., 1., 0.),
+        1.0,
+        diffuse,
+    )));
+
+    let glass = Surface::Refractive {
+        idx_of_refraction: 1.5,
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(0., 1., 0.),
+        1.0,
+        glass,
+    )));
+
+    let metal = Surface::Reflective {
+        albedo: Color::new(0.7, 0.6, 0.5),
+        fuzz: 0.0,
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(4., 1., 0.),
+        1.0,
+        metal,
+    )));
+
+    let white = Surface::Diffuse {
+        albedo: Texture::SolidColor(Color::from_one(0.73)),
+    };
+    let a_box = Element::Box(a_box(&Point::zero(), &Point::new(1.2, 1.2, 1.2), white));
+    world.add(Element::Translate {
+        offset: Vector3::new(-1.5, 0., 2.5),
+        object: Box::new(Element::new_rotate_y(30., a_box)),
+    });
+
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let samples_per_pixel = 16;
+    let max_depth = 50;
+
+    let fov = 20.0;
+    let look_from = Point::new(13.0, 2.0, 3.0);
+    let look_at = Point::new(0.0, 0.0, 0.0);
+    let view_up = Vector3::new(0.0, 1.0, 0.0);
+
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+
+    let background = Color::new(0.7, 0.8, 1.);
+
+    let camera = Camera::new(
+        aspect_ratio,
+        image_width,
+        samples_per_pixel,
+        max_depth,
+        fov,
+        look_from,
+        look_at,
+        view_up,
+        defocus_angle,
+        focus_dist,
+        background,
+    );
+
+    let film = camera.render_film(&world);
+
+    // Every AOV goes next to the beauty image as its own OpenEXR file, and so does
+    // the noisy beauty image itself.
+    for aov in Aov::ALL {
+        let mut out = BufWriter::new(File::create(format!("aov_{}.exr", aov.name()))?);
+        film.write_aov(aov, &mut out)?;
+    }
+    film.write_ppm(&mut BufWriter::new(File::create("noisy.ppm")?))?;
+
+    // The denoiser is guided by the albedo, normal and depth AOVs.
+    let denoised = Denoiser::default().denoise(&film);
+    denoised.write_ppm(&mut BufWriter::new(io::stdout().lock()))
+}
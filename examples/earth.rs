@@ -11,8 +11,7 @@ use std::io;
 fn main() -> io::Result<()> {
     let mut world = HittableList::default();
 
-    let earth = image::open("assets/earthmap.jpg").unwrap();
-    let earth_tx = Texture::Image(earth);
+    let earth_tx = Texture::load_image("assets/earthmap.jpg")?;
     let earth_surface = Surface::Diffuse { albedo: earth_tx };
     let globe = Element::Sphere(Sphere::new(Point::zero(), 2., earth_surface));
 
@@ -1,5 +1,5 @@
 use itertools::Itertools;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use raytracer::{
     camera::Camera,
     hittable::HittableList,
@@ -9,10 +9,13 @@ use raytracer::{
     texture::Texture,
     vector::{Color, Point, Vector3},
 };
-use std::io;
+use std::{fs, io, path::Path, time::Duration};
+
+const CHECKPOINT: &str = "final_scene.checkpoint";
 
 fn main() -> io::Result<()> {
-    let mut rng = rand::thread_rng();
+    // Seeded so the scene is the same every run, which resuming relies on.
+    let mut rng = StdRng::seed_from_u64(0);
 
     let mut world = HittableList::default();
 
@@ -82,8 +85,7 @@ fn main() -> io::Result<()> {
     ));
     world.add(boundary);
 
-    let earth = image::open("assets/earthmap.jpg").unwrap();
-    let earth_tx = Texture::Image(earth);
+    let earth_tx = Texture::load_image("assets/earthmap.jpg")?;
     let earth_surface = Surface::Diffuse { albedo: earth_tx };
     world.add(Element::Sphere(Sphere::new(
         Vector3::new(400., 200., 400.),
@@ -114,7 +116,7 @@ fn main() -> io::Result<()> {
 
     let background = Color::black();
 
-    let camera = Camera::new(
+    let mut camera = Camera::new(
         aspect_ratio,
         image_width,
         samples_per_pixel,
@@ -126,9 +128,23 @@ fn main() -> io::Result<()> {
         defocus_angle,
         focus_dist,
         background,
-    );
+    )
+    .with_progressive()
+    .with_checkpoints(CHECKPOINT, Duration::from_secs(60));
+
+    if Path::new(CHECKPOINT).exists() {
+        eprintln!("resuming from {CHECKPOINT}");
+        camera = camera.resume_from(CHECKPOINT)?;
+    }
 
     camera.render(&world);
 
+    // The render is done, so the next run should start over rather than resume
+    // and print this image again.
+    match fs::remove_file(CHECKPOINT) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
     Ok(())
 }
@@ -16,7 +16,7 @@ fn main() -> io::Result<()> {
         Point::new(0., -1000., 0.),
         1000.,
         Surface::Diffuse {
-            albedo: Texture::Perlin(Perlin),
+            albedo: Texture::Perlin(Perlin::default()),
         },
     )));
 
@@ -24,7 +24,7 @@ fn main() -> io::Result<()> {
         Point::new(0., 2., 0.),
         2.,
         Surface::Diffuse {
-            albedo: Texture::Turbulence(Perlin),
+            albedo: Texture::Turbulence(Perlin::new(7)),
         },
     )));
 
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use crate::{
+    hittable::HittableList,
+    material::Surface,
+    shape::Element,
+    vector::{Color, Point, Vector3},
+};
+
+/// An arbitrary output variable: an auxiliary image of what camera rays first
+/// hit, rendered alongside the beauty image for compositing and denoising.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// Surface color without lighting.
+    Albedo,
+    /// World-space shading normal, facing the camera.
+    Normal,
+    /// Distance from the camera along its viewing direction.
+    Depth,
+    /// World-space hit point.
+    Position,
+    /// Texture coordinates, in the red and green channels.
+    Uv,
+    /// Index of the surface among the distinct surfaces of the scene, in the
+    /// order they are tested for hits. Primitives sharing a surface through an
+    /// `Arc` share its id.
+    MaterialId,
+    /// Index of the element in the world's objects.
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::Uv,
+        Aov::MaterialId,
+        Aov::ObjectId,
+    ];
+
+    /// Short name for file and layer names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+        }
+    }
+}
+
+/// What one camera ray saw at its first hit.
+#[derive(Clone, Copy, Debug)]
+pub struct AovSample {
+    pub albedo: Color,
+    pub normal: Vector3,
+    pub depth: f32,
+    pub position: Point,
+    pub uv: (f32, f32),
+    pub material_id: Option<u32>,
+    pub object_id: Option<u32>,
+}
+
+/// The AOV samples taken in one pixel. Albedo and normal are averaged over every
+/// sample, with rays that hit nothing counting as black and zero, so they blend
+/// across silhouettes like the beauty image does. Depth, position and texture
+/// coordinates are averaged over the samples that hit something. The ids are
+/// those of the first sample that hit something, since ids cannot be blended.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AovPixel {
+    pub(crate) albedo: Color,
+    pub(crate) normal: Vector3,
+    pub(crate) depth: f32,
+    pub(crate) position: Point,
+    pub(crate) uv: (f32, f32),
+    pub(crate) samples: u32,
+    pub(crate) hits: u32,
+    pub(crate) material_id: Option<u32>,
+    pub(crate) object_id: Option<u32>,
+}
+
+impl AovPixel {
+    /// Adds the sample of a camera ray, or `None` if it hit nothing.
+    pub fn add(&mut self, sample: Option<&AovSample>) {
+        self.samples += 1;
+        let Some(sample) = sample else {
+            return;
+        };
+
+        self.albedo += sample.albedo;
+        self.normal += sample.normal;
+        self.depth += sample.depth;
+        self.position += sample.position;
+        self.uv.0 += sample.uv.0;
+        self.uv.1 += sample.uv.1;
+        self.hits += 1;
+        if self.hits == 1 {
+            self.material_id = sample.material_id;
+            self.object_id = sample.object_id;
+        }
+    }
+
+    /// Adds the samples of `other`, which must have been taken after those of
+    /// this pixel for the ids to stay those of the first hit.
+    pub fn merge(&mut self, other: &AovPixel) {
+        if self.hits == 0 {
+            self.material_id = other.material_id;
+            self.object_id = other.object_id;
+        }
+
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+        self.depth += other.depth;
+        self.position += other.position;
+        self.uv.0 += other.uv.0;
+        self.uv.1 += other.uv.1;
+        self.samples += other.samples;
+        self.hits += other.hits;
+    }
+
+    pub fn albedo(&self) -> Color {
+        self.albedo / self.samples.max(1) as f32
+    }
+
+    /// The average normal, which is shorter than unit length where the pixel
+    /// covers a curved surface or an edge.
+    pub fn normal(&self) -> Vector3 {
+        self.normal / self.samples.max(1) as f32
+    }
+
+    /// The average depth, or infinity if no sample hit anything.
+    pub fn depth(&self) -> f32 {
+        if self.hits == 0 {
+            return f32::INFINITY;
+        }
+
+        self.depth / self.hits as f32
+    }
+
+    pub fn position(&self) -> Point {
+        self.position / self.hits.max(1) as f32
+    }
+
+    pub fn uv(&self) -> (f32, f32) {
+        let hits = self.hits.max(1) as f32;
+        (self.uv.0 / hits, self.uv.1 / hits)
+    }
+
+    pub fn material_id(&self) -> Option<u32> {
+        self.material_id
+    }
+
+    pub fn object_id(&self) -> Option<u32> {
+        self.object_id
+    }
+
+    /// The value of `aov` as three channels. Scalars are repeated across all
+    /// three, and pixels without an id get `-1`.
+    pub fn channels(&self, aov: Aov) -> [f32; 3] {
+        let id = |id: Option<u32>| id.map_or(-1.0, |id| id as f32);
+        let vector = |v: Vector3| [v.x, v.y, v.z];
+
+        match aov {
+            Aov::Albedo => vector(self.albedo()),
+            Aov::Normal => vector(self.normal()),
+            Aov::Depth => [self.depth(); 3],
+            Aov::Position => vector(self.position()),
+            Aov::Uv => [self.uv().0, self.uv().1, 0.0],
+            Aov::MaterialId => [id(self.material_id); 3],
+            Aov::ObjectId => [id(self.object_id); 3],
+        }
+    }
+}
+
+/// Numbers the objects and surfaces of a scene for the id AOVs. Surfaces are told
+/// apart by address, so primitives sharing one `Arc<Surface>` share an id, while
+/// primitives built from clones of one surface get different ids.
+pub(crate) struct SceneIds {
+    objects: HashMap<usize, u32>,
+    materials: HashMap<usize, u32>,
+}
+
+impl SceneIds {
+    pub(crate) fn new(world: &HittableList) -> Self {
+        let objects = world
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(id, element)| (element as *const Element as usize, id as u32))
+            .collect();
+
+        let mut materials = HashMap::new();
+        for surface in world.objects.iter().flat_map(Element::surfaces) {
+            let id = materials.len() as u32;
+            materials
+                .entry(surface as *const Surface as usize)
+                .or_insert(id);
+        }
+
+        Self { objects, materials }
+    }
+
+    pub(crate) fn object_id(&self, element: &Element) -> Option<u32> {
+        self.objects
+            .get(&(element as *const Element as usize))
+            .copied()
+    }
+
+    pub(crate) fn material_id(&self, surface: &Surface) -> Option<u32> {
+        self.materials
+            .get(&(surface as *const Surface as usize))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::SceneIds;
+    use crate::{
+        hittable::HittableList,
+        material::Surface,
+        shape::{sphere::Sphere, Element},
+        test_scene::world,
+        vector::Point,
+    };
+
+    #[test]
+    fn material_ids_number_distinct_surfaces() {
+        let world = world();
+        let ids = SceneIds::new(&world);
+
+        let material_ids = |element: &Element| {
+            element
+                .surfaces()
+                .into_iter()
+                .map(|surface| ids.material_id(surface).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let per_object: Vec<_> = world.objects.iter().map(material_ids).collect();
+
+        // Four spheres with a surface each, then a box whose six sides share one.
+        assert_eq!(per_object[..4], [[0], [1], [2], [3]]);
+        assert_eq!(per_object[4], [4; 6]);
+    }
+
+    #[test]
+    fn primitives_sharing_a_surface_share_its_id() {
+        let shared = Arc::new(Surface::default());
+        let mut world = HittableList::default();
+        world.add(Element::Sphere(Sphere::new(
+            Point::zero(),
+            1.0,
+            shared.clone(),
+        )));
+        world.add(Element::Sphere(Sphere::new(
+            Point::zero(),
+            2.0,
+            Surface::default(),
+        )));
+        world.add(Element::Sphere(Sphere::new(Point::zero(), 3.0, shared)));
+        let ids = SceneIds::new(&world);
+
+        let material_id = |element: &Element| ids.material_id(element.surfaces()[0]);
+        let material_ids: Vec<_> = world.objects.iter().map(material_id).collect();
+
+        assert_eq!(material_ids, [Some(0), Some(1), Some(0)]);
+    }
+}
@@ -1,13 +1,32 @@
-use indicatif::ParallelProgressIterator;
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{self, BufWriter},
+    ops::{ControlFlow, Range},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use indicatif::ProgressBar;
 use itertools::Itertools;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::prelude::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
 
 use crate::{
-    hittable::{Hittable, HittableList},
-    interval::Interval,
+    aov::{AovSample, SceneIds},
+    checkpoint::{write_atomically, Checkpoint},
+    denoise::Denoiser,
+    distributed::{self, Handshake, Workers},
+    dot, error,
+    film::{Film, Pixel},
+    filter::PixelFilter,
+    hittable::{HitRecord, Hittable, HittableList},
     material::Material,
-    random,
-    ray::Ray,
+    ray::{Ray, RayDifferential},
+    sampler::{self, IndependentSampler, Sampler},
+    seed_random,
+    texture::TexCoord,
+    tile::{self, Rect, TileOrder},
     vector::{Color, Point, Vector3},
 };
 
@@ -17,6 +36,7 @@ pub struct Camera {
     samples_per_pixel: u32,
     max_depth: u32,
     center: Point,
+    view_direction: Vector3,
     pixel00_loc: Point,
     pixel_delta_u: Vector3,
     pixel_delta_v: Vector3,
@@ -24,9 +44,100 @@ pub struct Camera {
     defocus_disk_u: Vector3,
     defocus_disk_v: Vector3,
     background: Color,
+    sampler: Box<dyn Sampler>,
+    seed: u64,
+    adaptive: Option<AdaptiveSampling>,
+    progressive: bool,
+    snapshots: Option<Periodic>,
+    checkpoints: Option<Periodic>,
+    resume: Option<Checkpoint>,
+    crop: Rect,
+    tile_size: u32,
+    tile_order: TileOrder,
+    workers: usize,
+    filter: PixelFilter,
+    denoiser: Option<Denoiser>,
+    stop_criteria: Vec<StopCriterion>,
+}
+
+/// A condition that ends a render once it is met, checked after every pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopCriterion {
+    /// Stop once the render has run for this long.
+    Time(Duration),
+    /// Stop once every pixel has at least this many samples.
+    SamplesPerPixel(u32),
+    /// Stop once the estimated RMSE of the image luminance drops below this.
+    Error(f32),
+}
+
+impl StopCriterion {
+    fn is_met(&self, film: &Film, elapsed: Duration) -> bool {
+        match *self {
+            StopCriterion::Time(budget) => elapsed >= budget,
+            StopCriterion::SamplesPerPixel(samples) => film.min_samples() >= samples,
+            StopCriterion::Error(target) => film.estimated_rmse() <= target,
+        }
+    }
+}
+
+impl fmt::Display for StopCriterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopCriterion::Time(budget) => write!(f, "time budget of {budget:.1?}"),
+            StopCriterion::SamplesPerPixel(samples) => {
+                write!(f, "limit of {samples} samples per pixel")
+            }
+            StopCriterion::Error(target) => write!(f, "target RMSE of {target}"),
+        }
+    }
+}
+
+/// What a render actually achieved.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderReport {
+    pub passes: u32,
+    pub elapsed: Duration,
+    pub mean_samples_per_pixel: f32,
+    pub estimated_rmse: f32,
+    /// The criterion that ended the render, or `None` if it ran out of samples to
+    /// take or was stopped by the pass callback.
+    pub stopped_by: Option<StopCriterion>,
+}
+
+impl fmt::Display for RenderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} passes in {:.1?}, {:.1} samples per pixel on average, estimated RMSE {:.5}",
+            self.passes, self.elapsed, self.mean_samples_per_pixel, self.estimated_rmse
+        )?;
+        match &self.stopped_by {
+            Some(criterion) => write!(f, ", stopped by the {criterion}"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct AdaptiveSampling {
+    min_samples: u32,
+    threshold: f32,
+}
+
+/// A file rewritten after a pass whenever `interval` has passed since it was last
+/// written.
+#[derive(Clone, Debug)]
+struct Periodic {
+    path: PathBuf,
+    interval: Duration,
 }
 
+/// A sample of a pixel: its offset from the pixel center, its color and its AOVs.
+type PixelSample = ((f32, f32), Color, Option<AovSample>);
+
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f32,
         image_width: u32,
@@ -80,6 +191,7 @@ impl Camera {
             samples_per_pixel,
             max_depth,
             center,
+            view_direction: -w,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
@@ -87,83 +199,579 @@ impl Camera {
             defocus_disk_u,
             defocus_disk_v,
             background,
+            sampler: Box::new(IndependentSampler::default()),
+            seed: 0,
+            adaptive: None,
+            progressive: false,
+            snapshots: None,
+            checkpoints: None,
+            resume: None,
+            crop: Rect::new(0, 0, image_width, image_height),
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            workers: 0,
+            filter: PixelFilter::default(),
+            denoiser: None,
+            stop_criteria: Vec::new(),
+        }
+    }
+
+    /// Replaces the sampler that drives pixel, lens, time and material sampling.
+    /// The sampler is reseeded with the camera's seed.
+    pub fn with_sampler(mut self, sampler: impl Sampler + 'static) -> Self {
+        self.sampler = Box::new(sampler);
+        self.sampler.set_seed(self.seed);
+        self
+    }
+
+    /// Sets the render seed. Every pixel sample derives its random numbers from the
+    /// seed, the pixel and the sample index, so the same scene and seed always give
+    /// the same image, however the pixels are spread over threads.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.sampler.set_seed(seed);
+        self
+    }
+
+    /// Switches to adaptive sampling: pixels are rendered in passes of
+    /// `min_samples` samples, and a pixel stops once its estimated relative error
+    /// falls below `threshold` or it reaches `samples_per_pixel`.
+    pub fn with_adaptive_sampling(mut self, min_samples: u32, threshold: f32) -> Self {
+        self.adaptive = Some(AdaptiveSampling {
+            min_samples: min_samples.max(1).min(self.samples_per_pixel.max(1)),
+            threshold,
+        });
+        self
+    }
+
+    /// Renders one sample per pixel per pass instead of all samples at once, so
+    /// the whole image sharpens together and can be looked at between passes.
+    pub fn with_progressive(mut self) -> Self {
+        self.progressive = true;
+        self
+    }
+
+    /// Writes the film to `path` as a PPM after a pass whenever `interval` has
+    /// passed since the last snapshot, and once more when the render finishes.
+    pub fn with_snapshots(mut self, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.snapshots = Some(Periodic {
+            path: path.into(),
+            interval,
+        });
+        self
+    }
+
+    /// Saves a `Checkpoint` to `path` after a pass whenever `interval` has passed
+    /// since the last one, and once more when the render finishes. Checkpoints are
+    /// only taken between passes, so pair this with progressive or adaptive
+    /// rendering for long renders.
+    pub fn with_checkpoints(mut self, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.checkpoints = Some(Periodic {
+            path: path.into(),
+            interval,
+        });
+        self
+    }
+
+    /// Continues the render saved in the checkpoint at `path` instead of starting
+    /// from an empty film. With the same scene and camera settings, the result is
+    /// the same as a render that was never interrupted. The checkpoint is checked
+    /// against every setting that decides the samples of a pixel, so call this
+    /// after setting them.
+    pub fn resume_from(mut self, path: impl AsRef<Path>) -> error::Result<Self> {
+        let path = path.as_ref();
+        let image = Rect::new(0, 0, self.image_width, self.image_height);
+        let checkpoint = Checkpoint::load(path, image)?;
+        let invalid = |reason: String| error::Error::InvalidCheckpoint {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        if checkpoint.film.bounds() != self.crop {
+            return Err(invalid(format!(
+                "the film covers {}, but the camera renders {}",
+                checkpoint.film.bounds(),
+                self.crop
+            )));
+        }
+        if checkpoint.seed != self.seed {
+            return Err(invalid(format!(
+                "rendered with seed {}, but the camera uses seed {}",
+                checkpoint.seed, self.seed
+            )));
+        }
+        if checkpoint.samples_per_pixel != self.samples_per_pixel {
+            return Err(invalid(format!(
+                "rendered with {} samples per pixel, but the camera takes {}",
+                checkpoint.samples_per_pixel, self.samples_per_pixel
+            )));
         }
+        if checkpoint.filter != self.filter {
+            return Err(invalid(format!(
+                "rendered with the {:?} filter, but the camera uses {:?}",
+                checkpoint.filter, self.filter
+            )));
+        }
+        if checkpoint.sampler != self.sampler.description() {
+            return Err(invalid(format!(
+                "rendered with the {} sampler, but the camera uses the {} sampler",
+                checkpoint.sampler,
+                self.sampler.description()
+            )));
+        }
+        if checkpoint.fingerprint != self.fingerprint() {
+            return Err(invalid(
+                "rendered with other camera settings, such as the view, depth or \
+                 adaptive sampling"
+                    .to_owned(),
+            ));
+        }
+
+        self.resume = Some(checkpoint);
+        Ok(self)
+    }
+
+    /// Renders in tiles of `size` by `size` pixels, handed out to the render
+    /// threads in `order`. The default is 32 pixel tiles in scanline order.
+    pub fn with_tiles(mut self, size: u32, order: TileOrder) -> Self {
+        self.tile_size = size.max(1);
+        self.tile_order = order;
+        self
     }
 
+    /// Renders only the pixels inside `crop`, clipped to the image. The film and
+    /// the written images cover just the crop, while every pixel still gets the
+    /// same samples it would in a render of the whole image.
+    pub fn with_crop(mut self, crop: Rect) -> Self {
+        self.crop = crop.intersect(&Rect::new(0, 0, self.image_width, self.image_height));
+        self
+    }
+
+    /// Splits the render across `count` worker processes on this machine, which
+    /// take tiles from this process over pipes. The image is the same as a render
+    /// in one process.
+    ///
+    /// Workers run this executable again with the same arguments, so the scene
+    /// must be built deterministically, and the program must not read stdin or
+    /// write to stdout before it renders. A worker serves the first render it
+    /// reaches, and refuses if that is not the render the coordinator runs or its
+    /// camera settings differ; the coordinator then renders without workers.
+    ///
+    /// A worker traces the pixels of each tile on a thread pool with a thread per
+    /// core, so a single worker already keeps every core busy.
+    pub fn with_workers(mut self, count: usize) -> Self {
+        self.workers = count;
+        self
+    }
+
+    /// Sets the filter that reconstructs pixels from the samples around them. The
+    /// default box filter averages the samples taken in each pixel. Wider filters
+    /// need samples outside a crop window, so pixels at its edges get fewer.
+    pub fn with_filter(mut self, filter: PixelFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Denoises the image written by `render` and the snapshots. The film
+    /// returned by `render_film` is left noisy; pass it to `Denoiser::denoise`
+    /// for a denoised copy.
+    pub fn with_denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.denoiser = Some(denoiser);
+        self
+    }
+
+    /// Adds a condition that ends the render, which then runs in progressive
+    /// passes until any of its criteria is met. `samples_per_pixel` no longer
+    /// limits the render; add a `StopCriterion::SamplesPerPixel` for that.
+    pub fn with_stop_criterion(mut self, criterion: StopCriterion) -> Self {
+        self.stop_criteria.push(criterion);
+        self
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    /// Renders the scene, writes it to stdout as a PPM and reports what the render
+    /// achieved on stderr.
     pub fn render(&self, world: &HittableList) {
-        println!("P3");
-        println!("{} {}", self.image_width, self.image_height);
-        println!("255");
-
-        let pixels = (0..self.image_height)
-            .cartesian_product(0..self.image_width)
-            .collect::<Vec<(u32, u32)>>()
-            .into_par_iter()
-            .progress_count((self.image_height * self.image_width) as u64)
-            .map(|(v, u)| {
-                let scale_factor = (self.samples_per_pixel as f32).recip();
-
-                let pixel_color: Color = (0..self.samples_per_pixel)
-                    .map(|_| {
-                        let ray = self.get_ray(u, v);
-                        self.ray_color(&ray, world, self.max_depth)
+        let (film, report) = self.render_film_with(world, |_| ControlFlow::Continue(()));
+        eprintln!("{report}");
+
+        self.output(&film)
+            .write_ppm(&mut BufWriter::new(io::stdout().lock()))
+            .expect("failed to write the image to stdout");
+    }
+
+    /// Renders the scene into a film, keeping the per-pixel sample statistics.
+    pub fn render_film(&self, world: &HittableList) -> Film {
+        self.render_film_with(world, |_| ControlFlow::Continue(()))
+            .0
+    }
+
+    /// Renders the scene into a film, calling `on_pass` with the film after every
+    /// pass. Returning `ControlFlow::Break` from it stops the render early and
+    /// returns the film as it is.
+    pub fn render_film_with(
+        &self,
+        world: &HittableList,
+        mut on_pass: impl FnMut(&Film) -> ControlFlow<()>,
+    ) -> (Film, RenderReport) {
+        let ids = SceneIds::new(world);
+        let handshake = Handshake {
+            render: distributed::next_render(),
+            seed: self.seed,
+            fingerprint: self.fingerprint(),
+        };
+
+        if distributed::is_worker() {
+            let served = distributed::serve(
+                handshake,
+                self.crop,
+                |film, tile, pass_samples, max_samples| {
+                    self.render_tile(world, &ids, film, tile, pass_samples, max_samples)
+                },
+            );
+            if let Err(err) = served {
+                eprintln!("worker stopped: {err}");
+                process::exit(1);
+            }
+            process::exit(0);
+        }
+
+        let (mut film, mut passes, previous_elapsed) = match &self.resume {
+            Some(checkpoint) => (
+                checkpoint.film.clone(),
+                checkpoint.passes,
+                checkpoint.elapsed,
+            ),
+            None => (Film::with_bounds(self.crop), 0, Duration::ZERO),
+        };
+        let budgeted = !self.stop_criteria.is_empty();
+        let max_samples = if budgeted {
+            u32::MAX
+        } else {
+            self.samples_per_pixel
+        };
+        let pass_samples = if self.progressive || budgeted {
+            1
+        } else {
+            self.adaptive
+                .map_or(self.samples_per_pixel, |adaptive| adaptive.min_samples)
+        };
+
+        let progress = if budgeted {
+            ProgressBar::new_spinner()
+        } else {
+            ProgressBar::new(self.crop.area() * max_samples as u64)
+        };
+        progress.set_position(film.total_samples());
+
+        let start = Instant::now();
+        let elapsed = || previous_elapsed + start.elapsed();
+        let mut last_snapshot = start;
+        let mut last_checkpoint = start;
+        let mut stopped_by = None;
+
+        let tiles = tile::tiles(self.crop, self.tile_size, self.tile_order);
+        let workers = match self.workers {
+            0 => None,
+            count => Workers::spawn(count, handshake)
+                .map_err(|err| eprintln!("could not start workers, rendering here: {err}"))
+                .ok(),
+        };
+
+        loop {
+            let render_tile =
+                |tile| self.render_tile(world, &ids, &film, tile, pass_samples, max_samples);
+            let rendered = match &workers {
+                Some(workers) => workers.render_pass(
+                    &film,
+                    &tiles,
+                    pass_samples,
+                    max_samples,
+                    render_tile,
+                    |rendered| progress.inc(rendered.total_samples()),
+                ),
+                // Tiles go to whichever thread is free next, in order, and every
+                // tile renders into its own small film.
+                None => tiles
+                    .iter()
+                    .enumerate()
+                    .par_bridge()
+                    .filter_map(|(index, &tile)| {
+                        let rendered = render_tile(tile)?;
+                        progress.inc(rendered.total_samples());
+                        Some((index, rendered))
                     })
-                    .sum::<Vector3>()
-                    * scale_factor;
+                    .collect::<Vec<(usize, Film)>>(),
+            };
+            if rendered.is_empty() {
+                break;
+            }
+
+            // Tiles overlap where the filter reaches across their edges, so merge
+            // them in a fixed order to keep the sums, and the image, the same
+            // however the tiles were scheduled.
+            for (_, tile) in rendered.iter().sorted_by_key(|(index, _)| index) {
+                film.merge(tile);
+            }
+
+            if let Some(snapshots) = &self.snapshots {
+                if last_snapshot.elapsed() >= snapshots.interval {
+                    write_snapshot(&self.output(&film), &snapshots.path);
+                    last_snapshot = Instant::now();
+                }
+            }
 
-                let intensity = Interval::new(0.0, 0.999);
+            passes += 1;
+            if let Some(checkpoints) = &self.checkpoints {
+                if last_checkpoint.elapsed() >= checkpoints.interval {
+                    self.write_checkpoint(&film, passes, elapsed(), &checkpoints.path);
+                    last_checkpoint = Instant::now();
+                }
+            }
 
-                let color = Vector3::new(
-                    256.0 * intensity.clamp(pixel_color.x),
-                    256.0 * intensity.clamp(pixel_color.y),
-                    256.0 * intensity.clamp(pixel_color.z),
-                );
+            stopped_by = self
+                .stop_criteria
+                .iter()
+                .find(|criterion| criterion.is_met(&film, elapsed()))
+                .copied();
+            if stopped_by.is_some() || on_pass(&film).is_break() {
+                break;
+            }
+        }
+
+        progress.finish_and_clear();
+        if let Some(snapshots) = &self.snapshots {
+            write_snapshot(&self.output(&film), &snapshots.path);
+        }
+        if let Some(checkpoints) = &self.checkpoints {
+            self.write_checkpoint(&film, passes, elapsed(), &checkpoints.path);
+        }
+
+        let report = RenderReport {
+            passes,
+            elapsed: elapsed(),
+            mean_samples_per_pixel: film.mean_samples(),
+            estimated_rmse: film.estimated_rmse(),
+            stopped_by,
+        };
 
-                format!("{} {} {}", color.x as u8, color.y as u8, color.z as u8)
+        (film, report)
+    }
+
+    /// Takes the next pass of samples for the pixels of `tile` that still need
+    /// them, or returns `None` if none do.
+    fn render_tile(
+        &self,
+        world: &HittableList,
+        ids: &SceneIds,
+        film: &Film,
+        tile: Rect,
+        pass_samples: u32,
+        max_samples: u32,
+    ) -> Option<Film> {
+        // Samples near the edge of the tile also count towards the pixels just
+        // outside it, as far as the filter reaches from anywhere in a pixel.
+        let reach = (self.filter.radius() + 0.5).ceil() as u32;
+        let margin = Rect::new(
+            tile.x0.saturating_sub(reach),
+            tile.y0.saturating_sub(reach),
+            tile.x1 + reach,
+            tile.y1 + reach,
+        );
+        let pixels = tile
+            .pixels()
+            .filter(|&(u, v)| self.needs_samples(film.pixel(u, v), max_samples))
+            .collect::<Vec<_>>();
+        if pixels.is_empty() {
+            return None;
+        }
+
+        // Pixels trace in parallel, also inside a worker process, but their
+        // samples go into the film in pixel order, so the sums do not depend on
+        // how the pixels were scheduled.
+        let samples = pixels
+            .par_iter()
+            .map(|&(u, v)| {
+                let taken = film.pixel(u, v).samples();
+                let count = pass_samples.min(max_samples - taken);
+                self.sample_pixel(world, ids, (u, v), taken..taken + count)
             })
-            .collect::<Vec<String>>()
-            .join("\n");
+            .collect::<Vec<_>>();
+
+        let mut rendered = Film::with_bounds(margin.intersect(&self.crop));
+        for (&(u, v), samples) in pixels.iter().zip(samples) {
+            for (offset, color, aov) in samples {
+                rendered.add_sample((u, v), offset, color, &self.filter);
+                rendered.pixel_mut(u, v).aovs.add(aov.as_ref());
+            }
+        }
+
+        Some(rendered)
+    }
+
+    /// The film as it should be written out: denoised, if there is a denoiser.
+    fn output<'a>(&self, film: &'a Film) -> Cow<'a, Film> {
+        match &self.denoiser {
+            Some(denoiser) => Cow::Owned(denoiser.denoise(film)),
+            None => Cow::Borrowed(film),
+        }
+    }
+
+    /// Hashes the settings that decide the samples a pixel gets, for workers and
+    /// checkpoints to check that they belong to the same render.
+    fn fingerprint(&self) -> u64 {
+        let settings = format!(
+            "{} {} {} {} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {} {} {:?} {:?} {:?}",
+            self.image_width,
+            self.image_height,
+            self.samples_per_pixel,
+            self.max_depth,
+            self.center,
+            self.pixel00_loc,
+            self.pixel_delta_u,
+            self.pixel_delta_v,
+            self.defocus_disk_u,
+            self.defocus_disk_v,
+            self.background,
+            self.adaptive,
+            self.progressive,
+            self.sampler.description(),
+            self.crop,
+            self.filter,
+            self.seed,
+        );
+
+        sampler::hash_bytes(settings.as_bytes())
+    }
+
+    /// Saves a checkpoint, reporting a failure and carrying on with the render.
+    fn write_checkpoint(&self, film: &Film, passes: u32, elapsed: Duration, path: &Path) {
+        let checkpoint = Checkpoint {
+            film: film.clone(),
+            seed: self.seed,
+            passes,
+            elapsed,
+            samples_per_pixel: self.samples_per_pixel,
+            filter: self.filter,
+            sampler: self.sampler.description(),
+            fingerprint: self.fingerprint(),
+        };
 
-        println!("{pixels}");
+        if let Err(err) = checkpoint.save(path) {
+            eprintln!("failed to save checkpoint: {err}");
+        }
     }
 
-    fn get_ray(&self, u: u32, v: u32) -> Ray {
+    fn needs_samples(&self, pixel: &Pixel, max_samples: u32) -> bool {
+        if pixel.samples() >= max_samples {
+            return false;
+        }
+
+        match self.adaptive {
+            Some(adaptive) if pixel.samples() >= adaptive.min_samples => {
+                pixel.relative_error() > adaptive.threshold
+            }
+            _ => true,
+        }
+    }
+
+    /// Takes the samples with the given indices for one pixel, to be splatted into
+    /// a film in order.
+    fn sample_pixel(
+        &self,
+        world: &HittableList,
+        ids: &SceneIds,
+        (u, v): (u32, u32),
+        indices: Range<u32>,
+    ) -> Vec<PixelSample> {
+        let mut sampler = self.sampler.clone_box();
+
+        indices
+            .map(|i| {
+                sampler.start_pixel_sample((u, v), i);
+                seed_random(sampler::hash((u, v), i, !self.seed));
+
+                let (u1, u2) = sampler.get_2d();
+                let offset = (u1 - 0.5, u2 - 0.5);
+                let ray = self.get_ray(u, v, offset, sampler.as_mut());
+                let mut aov = None;
+                let color = self.trace(&ray, world, self.max_depth, sampler.as_mut(), |rec| {
+                    aov = Some(self.aov_sample(rec, ids));
+                });
+
+                (offset, color, aov)
+            })
+            .collect()
+    }
+
+    /// Builds the ray through pixel `(u, v)`, `offset` pixels from its center.
+    fn get_ray(&self, u: u32, v: u32, offset: (f32, f32), sampler: &mut dyn Sampler) -> Ray {
         let pixel_center =
             self.pixel00_loc + (u as f32 * self.pixel_delta_u) + (v as f32 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let pixel_offset = (self.pixel_delta_u * offset.0) + (self.pixel_delta_v * offset.1);
+        let pixel_sample = pixel_center + pixel_offset;
 
+        // Always consume the lens dimensions so later dimensions line up whether
+        // or not defocus blur is enabled.
+        let lens_sample = sampler.get_2d();
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(lens_sample)
         };
         let ray_direction = pixel_sample - ray_origin;
-        let ray_time = random();
+        let ray_time = sampler.get_1d();
 
-        Ray::new(ray_origin, ray_direction, ray_time)
-    }
+        // The differentials span one pixel, shrunk by the sample count since each
+        // sample only needs to filter over its share of the pixel.
+        let spacing = (self.samples_per_pixel as f32).sqrt().recip().max(0.125);
+        let differential = RayDifferential {
+            rx_origin: ray_origin,
+            rx_direction: ray_direction + self.pixel_delta_u * spacing,
+            ry_origin: ray_origin,
+            ry_direction: ray_direction + self.pixel_delta_v * spacing,
+        };
 
-    fn pixel_sample_square(&self) -> Vector3 {
-        let px = -0.5 + random();
-        let py = -0.5 + random();
+        Ray::new(ray_origin, ray_direction, ray_time).with_differential(Some(differential))
+    }
 
-        (self.pixel_delta_u * px) + (self.pixel_delta_v * py)
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        world: &HittableList,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        self.trace(ray, world, depth, sampler, |_| ())
     }
 
-    fn ray_color(&self, ray: &Ray, world: &HittableList, depth: u32) -> Color {
+    /// Like `ray_color`, but calls `on_hit` with the first hit of `ray`, if any.
+    fn trace<'a>(
+        &self,
+        ray: &Ray,
+        world: &'a HittableList,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+        on_hit: impl FnOnce(&HitRecord<'a>),
+    ) -> Color {
         if depth == 0 {
             return Color::black();
         }
 
-        if let Some(rec) = world.hit(ray, &(0.001, f32::MAX).into()) {
-            let emission_color = rec.material.emitted(rec.u, rec.v, &rec.p);
+        if let Some(mut rec) = world.hit(ray, &(0.001, f32::MAX).into()) {
+            rec.set_differentials(ray);
+            on_hit(&rec);
 
-            let Some((scattered, attenuation)) = rec.material.scatter(ray, &rec)
-            else {
+            let emission_color = rec.material.emitted(&TexCoord::from(&rec));
+
+            let Some((scattered, attenuation)) = rec.material.scatter(ray, &rec, sampler) else {
                 return emission_color;
             };
 
-            let scatter_color = attenuation * self.ray_color(&scattered, world, depth - 1);
+            let scatter_color = attenuation * self.ray_color(&scattered, world, depth - 1, sampler);
 
             return emission_color * 30. + scatter_color;
         }
@@ -171,8 +779,205 @@ impl Camera {
         self.background
     }
 
-    fn defocus_disk_sample(&self) -> Point {
-        let p = Point::random_in_unit_disk();
+    fn aov_sample(&self, rec: &HitRecord, ids: &SceneIds) -> AovSample {
+        AovSample {
+            albedo: rec.material.albedo(rec),
+            normal: rec.material.shading_normal(rec),
+            depth: dot(&(rec.p - self.center), &self.view_direction),
+            position: rec.p,
+            uv: (rec.u, rec.v),
+            material_id: ids.material_id(rec.material),
+            object_id: rec.element.and_then(|element| ids.object_id(element)),
+        }
+    }
+
+    fn defocus_disk_sample(&self, u: (f32, f32)) -> Point {
+        let p = Point::sample_unit_disk(u);
         self.center + (self.defocus_disk_u * p.x) + (self.defocus_disk_v * p.y)
     }
 }
+
+/// Writes the film with `write_atomically`, so viewers never see a half-written
+/// image. A failed snapshot is reported and the render carries on.
+fn write_snapshot(film: &Film, path: &Path) {
+    if let Err(err) = write_atomically(path, |out| film.write_ppm(out)) {
+        eprintln!("failed to write snapshot {}: {err}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, ops::ControlFlow, process, time::Duration};
+
+    use rayon::ThreadPoolBuilder;
+
+    use super::{Camera, StopCriterion};
+    use crate::{
+        filter::PixelFilter,
+        test_scene::{camera, film_bytes, world},
+        tile::{Rect, TileOrder},
+    };
+
+    #[test]
+    fn render_is_independent_of_thread_count() {
+        let world = world();
+        let camera = camera(64, 4).with_seed(7).with_tiles(8, TileOrder::Spiral);
+
+        let render = |threads| {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            film_bytes(&pool.install(|| camera.render_film(&world)))
+        };
+
+        assert!(render(1) == render(4));
+    }
+
+    #[test]
+    fn crops_render_the_pixels_of_the_full_image() {
+        let world = world();
+        let full = camera(32, 4).with_seed(2).render_film(&world);
+        let crop = Rect::new(5, 3, 21, 14);
+        let cropped = camera(32, 4)
+            .with_seed(2)
+            .with_crop(crop)
+            .with_tiles(4, TileOrder::Hilbert)
+            .render_film(&world);
+
+        assert_eq!(cropped.bounds(), crop);
+        for (x, y) in crop.pixels() {
+            let (expected, actual) = (full.pixel(x, y), cropped.pixel(x, y));
+            assert_eq!(actual.samples(), expected.samples());
+            let difference = actual.color() - expected.color();
+            assert!(difference.length() < 1e-5, "pixel ({x}, {y}) differs");
+        }
+    }
+
+    #[test]
+    fn crops_are_clipped_to_the_image() {
+        let cropped = camera(32, 1).with_crop(Rect::new(20, 10, 100, 100));
+
+        assert_eq!(
+            cropped.render_film(&world()).bounds(),
+            Rect::new(20, 10, 32, 18)
+        );
+    }
+
+    #[test]
+    fn progressive_passes_add_up_to_the_sample_budget() {
+        let mut totals = Vec::new();
+        let (film, report) = camera(16, 5)
+            .with_progressive()
+            .render_film_with(&world(), |film| {
+                totals.push(film.total_samples());
+                ControlFlow::Continue(())
+            });
+
+        let area = film.bounds().area();
+        assert_eq!(report.passes, 5);
+        assert_eq!(totals, (1..=5).map(|pass| pass * area).collect::<Vec<_>>());
+        assert_eq!(film.min_samples(), 5);
+    }
+
+    #[test]
+    fn pass_callbacks_can_stop_the_render() {
+        let mut passes = 0;
+        let (film, report) = camera(16, 5)
+            .with_progressive()
+            .render_film_with(&world(), |_| {
+                passes += 1;
+                if passes == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+
+        assert_eq!(report.passes, 2);
+        assert!(report.stopped_by.is_none());
+        assert_eq!(film.total_samples(), 2 * film.bounds().area());
+    }
+
+    #[test]
+    fn snapshots_are_written_when_the_render_finishes() {
+        let path = env::temp_dir().join(format!("raytracer-snapshot-{}.ppm", process::id()));
+        camera(16, 2)
+            .with_progressive()
+            .with_snapshots(&path, Duration::from_secs(3600))
+            .render_film(&world());
+
+        let snapshot = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(snapshot.starts_with("P3\n16 9\n255\n"), "{snapshot:.20}");
+    }
+
+    #[test]
+    fn sample_criteria_replace_the_sample_budget() {
+        let criterion = StopCriterion::SamplesPerPixel(3);
+        let (film, report) = camera(16, 1)
+            .with_stop_criterion(criterion)
+            .render_film_with(&world(), |_| ControlFlow::Continue(()));
+
+        assert_eq!(report.passes, 3);
+        assert_eq!(report.stopped_by, Some(criterion));
+        assert_eq!(report.mean_samples_per_pixel, 3.0);
+        assert_eq!(film.min_samples(), 3);
+        assert!(report
+            .to_string()
+            .ends_with("stopped by the limit of 3 samples per pixel"));
+    }
+
+    #[test]
+    fn error_criteria_wait_for_an_error_estimate() {
+        // The error is unknown until every pixel has two samples.
+        let criterion = StopCriterion::Error(1e6);
+        let (_, report) = camera(16, 1)
+            .with_stop_criterion(criterion)
+            .render_film_with(&world(), |_| ControlFlow::Continue(()));
+
+        assert_eq!(report.passes, 2);
+        assert_eq!(report.stopped_by, Some(criterion));
+        assert!(report.estimated_rmse <= 1e6);
+    }
+
+    #[test]
+    fn the_first_criterion_met_ends_the_render() {
+        let (_, report) = camera(16, 1)
+            .with_stop_criterion(StopCriterion::SamplesPerPixel(1000))
+            .with_stop_criterion(StopCriterion::Time(Duration::ZERO))
+            .render_film_with(&world(), |_| ControlFlow::Continue(()));
+
+        assert_eq!(report.passes, 1);
+        assert_eq!(report.stopped_by, Some(StopCriterion::Time(Duration::ZERO)));
+    }
+
+    #[test]
+    fn adaptive_sampling_accepts_zero_samples_per_pixel() {
+        let film = camera(16, 0)
+            .with_adaptive_sampling(4, 0.1)
+            .render_film(&world());
+
+        assert_eq!(film.total_samples(), 0);
+    }
+
+    #[test]
+    fn fingerprint_follows_render_settings() {
+        let fingerprint = |camera: Camera| camera.fingerprint();
+
+        assert_eq!(fingerprint(camera(64, 4)), fingerprint(camera(64, 4)));
+        assert_ne!(fingerprint(camera(64, 4)), fingerprint(camera(64, 8)));
+        assert_ne!(fingerprint(camera(64, 4)), fingerprint(camera(32, 4)));
+        assert_ne!(
+            fingerprint(camera(64, 4)),
+            fingerprint(camera(64, 4).with_seed(1))
+        );
+        assert_ne!(
+            fingerprint(camera(64, 4)),
+            fingerprint(camera(64, 4).with_filter(PixelFilter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5
+            }))
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    error::{Error, Result},
+    film::Film,
+    filter::PixelFilter,
+    tile::Rect,
+    vector::Color,
+};
+
+const MAGIC: &[u8; 8] = b"RTCHKPT\0";
+const VERSION: u32 = 7;
+/// Stands in for a missing material or object id.
+const NO_ID: u32 = u32::MAX;
+
+/// The state of a render between two passes: the film, how far the render got
+/// and the settings that decide which samples it takes.
+///
+/// Every pixel sample draws its random numbers from the seed, the pixel and the
+/// sample index, so the film and the seed are all a render needs to carry on
+/// exactly where it stopped.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub film: Film,
+    pub seed: u64,
+    pub passes: u32,
+    pub elapsed: Duration,
+    pub samples_per_pixel: u32,
+    pub filter: PixelFilter,
+    /// The `Sampler::description` of the sampler.
+    pub sampler: String,
+    /// Hash of every camera setting that decides the samples of a pixel, which
+    /// also catches the settings not stored above.
+    pub fingerprint: u64,
+}
+
+impl Checkpoint {
+    /// Saves the checkpoint with `write_atomically`, so a crash while saving
+    /// leaves the previous checkpoint intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        write_atomically(path, |out| self.write(out)).map_err(|source| Error::Write {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Loads the checkpoint at `path`, whose film must lie within `image`. The
+    /// bounds are checked before the film is allocated, so a corrupted file is
+    /// reported as invalid rather than asking for any amount of memory.
+    pub fn load(path: impl AsRef<Path>, image: Rect) -> Result<Self> {
+        let path = path.as_ref();
+        let io_error = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let invalid = |reason: &str| Error::InvalidCheckpoint {
+            path: path.to_path_buf(),
+            reason: reason.to_owned(),
+        };
+
+        let mut input = BufReader::new(File::open(path).map_err(io_error)?);
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic).map_err(io_error)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        if read_u32(&mut input).map_err(io_error)? != VERSION {
+            return Err(invalid("written by an incompatible version"));
+        }
+
+        Self::read(&mut input, image).map_err(|source| match source.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("file is truncated"),
+            io::ErrorKind::InvalidData => invalid(&source.to_string()),
+            _ => io_error(source),
+        })
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&self.passes.to_le_bytes())?;
+        out.write_all(&(self.elapsed.as_nanos() as u64).to_le_bytes())?;
+        out.write_all(&self.samples_per_pixel.to_le_bytes())?;
+        write_filter(out, &self.filter)?;
+        out.write_all(&(self.sampler.len() as u32).to_le_bytes())?;
+        out.write_all(self.sampler.as_bytes())?;
+        out.write_all(&self.fingerprint.to_le_bytes())?;
+        write_film(out, &self.film)
+    }
+
+    /// Reads everything after the magic and the version.
+    fn read(input: &mut impl Read, image: Rect) -> io::Result<Self> {
+        let seed = read_u64(input)?;
+        let passes = read_u32(input)?;
+        let elapsed = Duration::from_nanos(read_u64(input)?);
+        let samples_per_pixel = read_u32(input)?;
+        let filter = read_filter(input)?;
+        let length = read_u32(input)?;
+        let mut sampler = Vec::new();
+        input.take(length.into()).read_to_end(&mut sampler)?;
+        if sampler.len() != length as usize {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let sampler = String::from_utf8(sampler)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let fingerprint = read_u64(input)?;
+        let film = read_film(input, image)?;
+
+        Ok(Self {
+            film,
+            seed,
+            passes,
+            elapsed,
+            samples_per_pixel,
+            filter,
+            sampler,
+            fingerprint,
+        })
+    }
+}
+
+/// Writes a file next to `path` with `write` and renames it into place, so
+/// readers never see it half-written.
+pub(crate) fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+
+    let mut out = BufWriter::new(File::create(&partial)?);
+    write(&mut out)?;
+    out.flush()?;
+
+    fs::rename(&partial, path)
+}
+
+/// Writes the kind of filter as a tag, followed by its parameters padded to three.
+fn write_filter(out: &mut impl Write, filter: &PixelFilter) -> io::Result<()> {
+    let (tag, parameters) = match *filter {
+        PixelFilter::Box { radius } => (0u32, [radius, 0.0, 0.0]),
+        PixelFilter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+        PixelFilter::Gaussian { radius, sigma } => (2, [radius, sigma, 0.0]),
+        PixelFilter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+        PixelFilter::Lanczos { radius, tau } => (4, [radius, tau, 0.0]),
+    };
+
+    out.write_all(&tag.to_le_bytes())?;
+    for value in parameters {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_filter(input: &mut impl Read) -> io::Result<PixelFilter> {
+    let tag = read_u32(input)?;
+    let [radius, a, b] = [read_f32(input)?, read_f32(input)?, read_f32(input)?];
+
+    match tag {
+        0 => Ok(PixelFilter::Box { radius }),
+        1 => Ok(PixelFilter::Tent { radius }),
+        2 => Ok(PixelFilter::Gaussian { radius, sigma: a }),
+        3 => Ok(PixelFilter::Mitchell { radius, b: a, c: b }),
+        4 => Ok(PixelFilter::Lanczos { radius, tau: a }),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown filter {tag}"),
+        )),
+    }
+}
+
+/// Writes the bounds and the raw statistics of every pixel of `film`, little-endian.
+pub(crate) fn write_film(out: &mut impl Write, film: &Film) -> io::Result<()> {
+    let bounds = film.bounds();
+    for value in [bounds.x0, bounds.y0, bounds.x1, bounds.y1] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+
+    for pixel in &film.pixels {
+        for value in [
+            pixel.sum.x,
+            pixel.sum.y,
+            pixel.sum.z,
+            pixel.luminance_squares,
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&pixel.samples.to_le_bytes())?;
+        for value in [
+            pixel.weighted_sum.x,
+            pixel.weighted_sum.y,
+            pixel.weighted_sum.z,
+            pixel.weight_sum,
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+
+        let aovs = &pixel.aovs;
+        for value in [
+            aovs.albedo.x,
+            aovs.albedo.y,
+            aovs.albedo.z,
+            aovs.normal.x,
+            aovs.normal.y,
+            aovs.normal.z,
+            aovs.depth,
+            aovs.position.x,
+            aovs.position.y,
+            aovs.position.z,
+            aovs.uv.0,
+            aovs.uv.1,
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        for value in [
+            aovs.samples,
+            aovs.hits,
+            aovs.material_id.unwrap_or(NO_ID),
+            aovs.object_id.unwrap_or(NO_ID),
+        ] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a film written by `write_film`, failing with `InvalidData` unless its
+/// bounds lie within `within`.
+pub(crate) fn read_film(input: &mut impl Read, within: Rect) -> io::Result<Film> {
+    let [x0, y0, x1, y1] = [
+        read_u32(input)?,
+        read_u32(input)?,
+        read_u32(input)?,
+        read_u32(input)?,
+    ];
+    let bounds = Rect::new(x0, y0, x1, y1);
+    if x1 < x0 || y1 < y0 || bounds.intersect(&within) != bounds {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("film from ({x0}, {y0}) to ({x1}, {y1}) lies outside {within}"),
+        ));
+    }
+
+    let mut film = Film::with_bounds(bounds);
+    for pixel in &mut film.pixels {
+        pixel.sum = read_color(input)?;
+        pixel.luminance_squares = read_f32(input)?;
+        pixel.samples = read_u32(input)?;
+        pixel.weighted_sum = read_color(input)?;
+        pixel.weight_sum = read_f32(input)?;
+
+        let aovs = &mut pixel.aovs;
+        aovs.albedo = read_color(input)?;
+        aovs.normal = read_color(input)?;
+        aovs.depth = read_f32(input)?;
+        aovs.position = read_color(input)?;
+        aovs.uv = (read_f32(input)?, read_f32(input)?);
+        aovs.samples = read_u32(input)?;
+        aovs.hits = read_u32(input)?;
+        aovs.material_id = Some(read_u32(input)?).filter(|&id| id != NO_ID);
+        aovs.object_id = Some(read_u32(input)?).filter(|&id| id != NO_ID);
+    }
+
+    Ok(film)
+}
+
+pub(crate) fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(input: &mut impl Read) -> io::Result<f32> {
+    read_u32(input).map(f32::from_bits)
+}
+
+fn read_color(input: &mut impl Read) -> io::Result<Color> {
+    let x = read_f32(input)?;
+    let y = read_f32(input)?;
+    let z = read_f32(input)?;
+    Ok(Color::new(x, y, z))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, ops::ControlFlow, path::PathBuf, process, time::Duration};
+
+    use super::{read_film, write_film, Checkpoint};
+    use crate::{
+        error::Error,
+        film::{Film, Pixel},
+        filter::PixelFilter,
+        test_scene::{camera, film_bytes, world},
+        tile::Rect,
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("raytracer-{name}-{}.checkpoint", process::id()))
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let world = world();
+        let path = temp_path("resume");
+        let camera = || camera(32, 4).with_seed(3).with_progressive();
+
+        let uninterrupted = camera().render_film(&world);
+
+        let mut passes = 0;
+        let (interrupted, _) = camera()
+            .with_checkpoints(&path, Duration::MAX)
+            .render_film_with(&world, |_| {
+                passes += 1;
+                if passes == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+        let checkpoint = Checkpoint::load(&path, Rect::new(0, 0, 32, 18));
+        let resumed = camera()
+            .resume_from(&path)
+            .map(|camera| camera.render_film(&world));
+        let _ = fs::remove_file(&path);
+
+        let checkpoint = checkpoint.unwrap();
+        assert_eq!(checkpoint.passes, 2);
+        assert!(film_bytes(&checkpoint.film) == film_bytes(&interrupted));
+        assert!(film_bytes(&resumed.unwrap()) == film_bytes(&uninterrupted));
+    }
+
+    #[test]
+    fn film_round_trips_through_bytes() {
+        let film = camera(32, 2).render_film(&world());
+
+        let bytes = film_bytes(&film);
+        let read = read_film(&mut bytes.as_slice(), film.bounds()).unwrap();
+
+        let rgb = |pixel: &Pixel| {
+            let color = pixel.color();
+            [color.x, color.y, color.z]
+        };
+        assert_eq!(read.bounds(), film.bounds());
+        for (read, pixel) in read.pixels.iter().zip(&film.pixels) {
+            assert_eq!(rgb(read), rgb(pixel));
+            assert_eq!(read.aovs().material_id(), pixel.aovs().material_id());
+            assert_eq!(read.aovs().object_id(), pixel.aovs().object_id());
+        }
+        let mut rewritten = Vec::new();
+        write_film(&mut rewritten, &read).unwrap();
+        assert!(rewritten == bytes);
+    }
+
+    #[test]
+    fn corrupted_film_bounds_are_rejected() {
+        let image = Rect::new(0, 0, 4, 4);
+        let checkpoint = Checkpoint {
+            film: Film::with_bounds(image),
+            seed: 0,
+            passes: 1,
+            elapsed: Duration::ZERO,
+            samples_per_pixel: 1,
+            filter: PixelFilter::default(),
+            sampler: "independent".to_owned(),
+            fingerprint: 0,
+        };
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+
+        // Point the far corner of the film at the end of the address space.
+        let film_start = bytes.len() - film_bytes(&checkpoint.film).len();
+        bytes[film_start + 8..film_start + 16].fill(0xff);
+        let path = temp_path("corrupted");
+        fs::write(&path, &bytes).unwrap();
+        let loaded = Checkpoint::load(&path, image);
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(loaded, Err(Error::InvalidCheckpoint { .. })));
+    }
+
+    #[test]
+    fn checkpoints_of_other_settings_are_rejected() {
+        let world = world();
+        let path = temp_path("settings");
+        let camera = || camera(32, 4).with_progressive();
+
+        camera()
+            .with_checkpoints(&path, Duration::MAX)
+            .render_film_with(&world, |_| ControlFlow::Break(()));
+        let same = camera().resume_from(&path).map(|_| ());
+        let adaptive = camera()
+            .with_adaptive_sampling(2, 0.1)
+            .resume_from(&path)
+            .map(|_| ());
+        let _ = fs::remove_file(&path);
+
+        assert!(same.is_ok());
+        assert!(matches!(adaptive, Err(Error::InvalidCheckpoint { .. })));
+    }
+}
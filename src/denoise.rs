@@ -0,0 +1,362 @@
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    dot,
+    film::Film,
+    vector::{Color, Vector3},
+};
+
+/// The B3 spline the à-trous wavelet is built from, spread wider every pass.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo channels below this are left alone when the albedo is divided out, so
+/// black surfaces and the background keep their color.
+const MIN_ALBEDO: f32 = 0.01;
+
+/// Edge-avoiding à-trous wavelet denoiser, guided by the albedo, normal and depth
+/// AOVs of the film.
+///
+/// The albedo is divided out first, so textures stay sharp and only the lighting
+/// is blurred. Every pass then averages each pixel with neighbours twice as far
+/// away as the pass before, weighting them down where the guides differ or the
+/// luminance differs by more than the noise of the pixel explains.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// Number of passes; the filter reaches `2 * (2^iterations - 1)` pixels.
+    pub iterations: u32,
+    /// How many standard deviations of noise luminance differences may span
+    /// before a neighbour stops counting.
+    pub luminance_sigma: f32,
+    /// Exponent on the cosine between normals; higher keeps creases sharper.
+    pub normal_power: f32,
+    /// How far depths may differ, relative to the depth gradient at the pixel.
+    pub depth_sigma: f32,
+    /// How far albedos may differ.
+    pub albedo_sigma: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            luminance_sigma: 4.0,
+            normal_power: 128.0,
+            depth_sigma: 1.0,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// The guides and the noisy lighting of one pixel.
+#[derive(Clone, Copy)]
+struct Texel {
+    albedo: Color,
+    normal: Vector3,
+    depth: f32,
+    depth_gradient: f32,
+}
+
+impl Denoiser {
+    /// Returns a copy of `film` with the reconstructed color of every pixel
+    /// denoised. The sample statistics are left as they were, so the copy is for
+    /// output only and should not be rendered into further.
+    pub fn denoise(&self, film: &Film) -> Film {
+        let width = film.width() as usize;
+        let height = film.height() as usize;
+
+        let mut texels: Vec<Texel> = film
+            .pixels
+            .iter()
+            .map(|pixel| {
+                let aovs = pixel.aovs();
+                let normal = aovs.normal();
+                Texel {
+                    albedo: aovs.albedo(),
+                    normal: if normal.near_zero() {
+                        Vector3::zero()
+                    } else {
+                        normal.unit()
+                    },
+                    depth: aovs.depth(),
+                    depth_gradient: 0.0,
+                }
+            })
+            .collect();
+        for y in 0..height {
+            for x in 0..width {
+                texels[y * width + x].depth_gradient = depth_gradient(&texels, width, height, x, y);
+            }
+        }
+
+        let mut lighting: Vec<Color> = film
+            .pixels
+            .iter()
+            .zip(&texels)
+            .map(|(pixel, texel)| demodulate(pixel.color(), texel.albedo))
+            .collect();
+        let mut variance: Vec<f32> = film
+            .pixels
+            .iter()
+            .zip(&texels)
+            .enumerate()
+            .map(|(index, (pixel, texel))| {
+                if pixel.samples() < 2 {
+                    return spatial_variance(&lighting, width, height, index);
+                }
+                let albedo = albedo_factor(texel.albedo).luminance();
+                pixel.standard_error().powi(2) / (albedo * albedo)
+            })
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let blurred_variance = blur_variance(&variance, width, height);
+
+            (lighting, variance) = (0..width * height)
+                .into_par_iter()
+                .map(|index| {
+                    self.filter_pixel(
+                        (index % width, index / width),
+                        (width, height),
+                        step,
+                        &texels,
+                        &lighting,
+                        &variance,
+                        blurred_variance[index],
+                    )
+                })
+                .unzip();
+        }
+
+        let mut denoised = film.clone();
+        for ((pixel, texel), color) in denoised.pixels.iter_mut().zip(&texels).zip(lighting) {
+            pixel.weighted_sum = remodulate(color, texel.albedo) * pixel.weight_sum;
+        }
+
+        denoised
+    }
+
+    /// One pass of the filter for one pixel. Returns the filtered lighting and
+    /// its variance.
+    #[allow(clippy::too_many_arguments)]
+    fn filter_pixel(
+        &self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+        step: usize,
+        texels: &[Texel],
+        lighting: &[Color],
+        variance: &[f32],
+        blurred_variance: f32,
+    ) -> (Color, f32) {
+        let center = y * width + x;
+        let texel = &texels[center];
+        let luminance = lighting[center].luminance();
+        let luminance_scale = self.luminance_sigma * blurred_variance.sqrt() + 1e-4;
+
+        // The center pixel counts with its full kernel weight whatever the other
+        // weights come to, so the sums never vanish.
+        let center_weight = KERNEL[2] * KERNEL[2];
+        let mut color_sum = lighting[center] * center_weight;
+        let mut variance_sum = center_weight * center_weight * variance[center];
+        let mut weight_sum = center_weight;
+
+        for (j, ky) in KERNEL.iter().enumerate() {
+            let Some(qy) = (y + j * step)
+                .checked_sub(2 * step)
+                .filter(|&qy| qy < height)
+            else {
+                continue;
+            };
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let Some(qx) = (x + i * step)
+                    .checked_sub(2 * step)
+                    .filter(|&qx| qx < width)
+                else {
+                    continue;
+                };
+                if (i, j) == (2, 2) {
+                    continue;
+                }
+
+                let neighbour = qy * width + qx;
+                let other = &texels[neighbour];
+                let distance = (i.abs_diff(2).pow(2) + j.abs_diff(2).pow(2)) as f32;
+                let distance = distance.sqrt() * step as f32;
+
+                let luminance_weight =
+                    (-(luminance - lighting[neighbour].luminance()).abs() / luminance_scale).exp();
+                let weight = kx
+                    * ky
+                    * luminance_weight
+                    * self.normal_weight(texel, other)
+                    * self.depth_weight(texel, other, distance)
+                    * self.albedo_weight(texel, other);
+                if weight <= 0.0 || !weight.is_finite() {
+                    continue;
+                }
+
+                color_sum += lighting[neighbour] * weight;
+                variance_sum += weight * weight * variance[neighbour];
+                weight_sum += weight;
+            }
+        }
+
+        (
+            color_sum / weight_sum,
+            variance_sum / (weight_sum * weight_sum),
+        )
+    }
+
+    fn normal_weight(&self, texel: &Texel, other: &Texel) -> f32 {
+        match (texel.normal.near_zero(), other.normal.near_zero()) {
+            (true, true) => 1.0,
+            (false, false) => dot(&texel.normal, &other.normal)
+                .max(0.0)
+                .powf(self.normal_power),
+            _ => 0.0,
+        }
+    }
+
+    fn depth_weight(&self, texel: &Texel, other: &Texel, distance: f32) -> f32 {
+        match (texel.depth.is_finite(), other.depth.is_finite()) {
+            (true, true) => {
+                let scale = self.depth_sigma * texel.depth_gradient * distance + 1e-3;
+                (-(texel.depth - other.depth).abs() / scale).exp()
+            }
+            (false, false) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn albedo_weight(&self, texel: &Texel, other: &Texel) -> f32 {
+        let difference = texel.albedo - other.albedo;
+        (-dot(&difference, &difference) / (self.albedo_sigma * self.albedo_sigma)).exp()
+    }
+}
+
+/// The largest change in depth to a neighbouring pixel, per pixel.
+fn depth_gradient(texels: &[Texel], width: usize, height: usize, x: usize, y: usize) -> f32 {
+    let depth = texels[y * width + x].depth;
+    if !depth.is_finite() {
+        return 0.0;
+    }
+
+    let neighbours = [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ];
+    neighbours
+        .into_iter()
+        .filter(|&(nx, ny)| nx < width && ny < height)
+        .map(|(nx, ny)| texels[ny * width + nx].depth)
+        .filter(|other| other.is_finite())
+        .map(|other| (other - depth).abs())
+        .fold(0.0, f32::max)
+}
+
+/// Variance of the luminance over the 3x3 pixels around `index`, for pixels with
+/// too few samples to estimate their own.
+fn spatial_variance(lighting: &[Color], width: usize, height: usize, index: usize) -> f32 {
+    let (x, y) = (index % width, index / width);
+    let mut sum = 0.0;
+    let mut squares = 0.0;
+    let mut count = 0.0;
+    for qy in y.saturating_sub(1)..(y + 2).min(height) {
+        for qx in x.saturating_sub(1)..(x + 2).min(width) {
+            let luminance = lighting[qy * width + qx].luminance();
+            sum += luminance;
+            squares += luminance * luminance;
+            count += 1.0;
+        }
+    }
+
+    let mean = sum / count;
+    (squares / count - mean * mean).max(0.0)
+}
+
+/// Spreads the variance over a 3x3 Gaussian, since a handful of samples make
+/// for a noisy estimate of it.
+fn blur_variance(variance: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let kernel = [0.25, 0.5, 0.25];
+
+    (0..width * height)
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let mut sum = 0.0;
+            let mut weight_sum = 0.0;
+            for (j, ky) in kernel.iter().enumerate() {
+                for (i, kx) in kernel.iter().enumerate() {
+                    let (qx, qy) = ((x + i).wrapping_sub(1), (y + j).wrapping_sub(1));
+                    if qx < width && qy < height {
+                        sum += kx * ky * variance[qy * width + qx];
+                        weight_sum += kx * ky;
+                    }
+                }
+            }
+            sum / weight_sum
+        })
+        .collect()
+}
+
+/// The albedo to divide out of a color, with channels too dark to divide by
+/// replaced by one.
+fn albedo_factor(albedo: Color) -> Color {
+    let channel = |value: f32| if value > MIN_ALBEDO { value } else { 1.0 };
+    Color::new(channel(albedo.x), channel(albedo.y), channel(albedo.z))
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    let factor = albedo_factor(albedo);
+    Color::new(color.x / factor.x, color.y / factor.y, color.z / factor.z)
+}
+
+fn remodulate(color: Color, albedo: Color) -> Color {
+    color * albedo_factor(albedo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Denoiser;
+    use crate::{
+        film::Film,
+        test_scene::{camera, world},
+    };
+
+    /// Root-mean-square difference between the colors of two films, over all
+    /// channels.
+    fn rmse(film: &Film, reference: &Film) -> f32 {
+        let squared_error: f32 = film
+            .pixels
+            .iter()
+            .zip(&reference.pixels)
+            .map(|(pixel, reference)| {
+                let difference = pixel.color() - reference.color();
+                difference.dot(&difference)
+            })
+            .sum();
+
+        (squared_error / (3 * film.pixels.len()) as f32).sqrt()
+    }
+
+    #[test]
+    fn denoising_brings_low_sample_renders_closer_to_the_reference() {
+        let world = world();
+        let reference = camera(64, 256).with_seed(1).render_film(&world);
+
+        for samples_per_pixel in [1, 4] {
+            let noisy = camera(64, samples_per_pixel).render_film(&world);
+            let denoised = Denoiser::default().denoise(&noisy);
+
+            let noisy_error = rmse(&noisy, &reference);
+            let denoised_error = rmse(&denoised, &reference);
+            assert!(
+                denoised_error < 0.9 * noisy_error,
+                "{samples_per_pixel} spp: RMSE {noisy_error} denoised to {denoised_error}, \
+                 expected a drop of 20-30% on this scene"
+            );
+        }
+    }
+}
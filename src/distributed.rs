@@ -0,0 +1,308 @@
+use std::{
+    env,
+    io::{self, BufReader, BufWriter, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    checkpoint::{read_film, read_u32, read_u64, write_film},
+    film::Film,
+    tile::Rect,
+};
+
+/// Set in the environment of worker processes.
+pub const WORKER_ENV: &str = "RAYTRACER_WORKER";
+
+pub fn is_worker() -> bool {
+    env::var_os(WORKER_ENV).is_some()
+}
+
+/// How many renders this process has started.
+static RENDERS: AtomicU32 = AtomicU32::new(0);
+
+/// Counts a render and returns its index among the renders of this process.
+pub(crate) fn next_render() -> u32 {
+    RENDERS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Identifies a render, so a worker only serves the render the coordinator is
+/// running. A program that renders more than once starts every worker from the
+/// beginning, and a worker could otherwise answer with the settings of an earlier
+/// render.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Handshake {
+    /// Index of the render among the renders of the program.
+    pub(crate) render: u32,
+    pub(crate) seed: u64,
+    /// Hash of the camera settings that decide the samples of a tile.
+    pub(crate) fingerprint: u64,
+}
+
+impl Handshake {
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.render.to_le_bytes())?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&self.fingerprint.to_le_bytes())?;
+        out.flush()
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<Self> {
+        Ok(Self {
+            render: read_u32(input)?,
+            seed: read_u64(input)?,
+            fingerprint: read_u64(input)?,
+        })
+    }
+}
+
+/// A tile to render: the film of the tile as the coordinator has it, and how many
+/// samples to take.
+struct Request {
+    film: Film,
+    pass_samples: u32,
+    max_samples: u32,
+}
+
+impl Request {
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.pass_samples.to_le_bytes())?;
+        out.write_all(&self.max_samples.to_le_bytes())?;
+        write_film(out, &self.film)?;
+        out.flush()
+    }
+
+    /// Reads the next request for a tile within `crop`, or `None` once the
+    /// coordinator has hung up.
+    fn read(input: &mut impl Read, crop: Rect) -> io::Result<Option<Self>> {
+        let pass_samples = match read_u32(input) {
+            Ok(samples) => samples,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(Self {
+            pass_samples,
+            max_samples: read_u32(input)?,
+            film: read_film(input, crop)?,
+        }))
+    }
+}
+
+fn write_response(out: &mut impl Write, rendered: Option<&Film>) -> io::Result<()> {
+    match rendered {
+        Some(film) => {
+            out.write_all(&[1])?;
+            write_film(out, film)?;
+        }
+        None => out.write_all(&[0])?,
+    }
+    out.flush()
+}
+
+fn read_response(input: &mut impl Read, crop: Rect) -> io::Result<Option<Film>> {
+    let mut rendered = [0];
+    input.read_exact(&mut rendered)?;
+
+    match rendered[0] {
+        0 => Ok(None),
+        _ => read_film(input, crop).map(Some),
+    }
+}
+
+/// Checks the coordinator's handshake against `handshake`, the render this
+/// worker reached, then answers tile requests on stdin with `render_tile` until
+/// stdin closes. A worker at a different render, or with different settings,
+/// refuses and returns an error, and the coordinator renders without it.
+/// Requests for tiles outside `crop` are an error too.
+pub(crate) fn serve(
+    handshake: Handshake,
+    crop: Rect,
+    render_tile: impl Fn(&Film, Rect, u32, u32) -> Option<Film>,
+) -> io::Result<()> {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    let expected = Handshake::read(&mut input)?;
+    let accepted = expected == handshake;
+    out.write_all(&[accepted as u8])?;
+    out.flush()?;
+    if !accepted {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("coordinator runs {expected:?}, but this worker reached {handshake:?}"),
+        ));
+    }
+
+    while let Some(request) = Request::read(&mut input, crop)? {
+        let tile = request.film.bounds();
+        let rendered = render_tile(
+            &request.film,
+            tile,
+            request.pass_samples,
+            request.max_samples,
+        );
+        write_response(&mut out, rendered.as_ref())?;
+    }
+
+    Ok(())
+}
+
+struct Worker {
+    child: Child,
+    input: BufWriter<ChildStdin>,
+    output: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    fn spawn(handshake: Handshake) -> io::Result<Self> {
+        let mut child = Command::new(env::current_exe()?)
+            .args(env::args_os().skip(1))
+            .env(WORKER_ENV, "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let mut input = BufWriter::new(child.stdin.take().expect("worker stdin is piped"));
+        let mut output = BufReader::new(child.stdout.take().expect("worker stdout is piped"));
+
+        let mut accepted = [0];
+        let answered = handshake
+            .write(&mut input)
+            .and_then(|()| output.read_exact(&mut accepted));
+        let worker = Self {
+            child,
+            input,
+            output,
+        };
+        if answered.is_err() || accepted[0] == 0 {
+            worker.close();
+            answered?;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the worker reached a different render or different settings",
+            ));
+        }
+
+        Ok(worker)
+    }
+
+    /// Closes the worker's pipes, which tells it to exit, and waits for it so it
+    /// does not linger as a zombie.
+    fn close(self) {
+        let Self {
+            mut child,
+            input,
+            output,
+        } = self;
+        drop((input, output));
+        let _ = child.wait();
+    }
+
+    fn render(&mut self, request: &Request, crop: Rect) -> io::Result<Option<Film>> {
+        request.write(&mut self.input)?;
+        read_response(&mut self.output, crop)
+    }
+}
+
+/// A pool of worker processes. Every worker runs this executable again, with the
+/// same arguments and `WORKER_ENV` set, so it builds the same scene and camera;
+/// when it reaches its first render it checks the handshake of the pool and
+/// serves tile requests on its stdin and stdout instead, and exits once the
+/// pool closes its stdin.
+pub(crate) struct Workers {
+    workers: Vec<Mutex<Option<Worker>>>,
+}
+
+impl Workers {
+    /// Starts `count` workers for the render identified by `handshake`.
+    /// If one of them fails to start, the others are closed again.
+    pub(crate) fn spawn(count: usize, handshake: Handshake) -> io::Result<Self> {
+        let mut workers = Self {
+            workers: Vec::with_capacity(count),
+        };
+        for _ in 0..count {
+            // Dropping `workers` on error closes the workers started so far.
+            workers
+                .workers
+                .push(Mutex::new(Some(Worker::spawn(handshake)?)));
+        }
+
+        Ok(workers)
+    }
+
+    /// Renders a pass over `tiles`, handing each tile to the next idle worker, and
+    /// returns the rendered tiles with their index in `tiles`.
+    /// A worker that fails is dropped and its tiles are rendered with
+    /// `render_local` instead, so a crashed worker never loses samples.
+    pub(crate) fn render_pass(
+        &self,
+        film: &Film,
+        tiles: &[Rect],
+        pass_samples: u32,
+        max_samples: u32,
+        render_local: impl Fn(Rect) -> Option<Film> + Sync,
+        on_tile: impl Fn(&Film) + Sync,
+    ) -> Vec<(usize, Film)> {
+        let next = AtomicUsize::new(0);
+        let rendered = Mutex::new(Vec::new());
+
+        thread::scope(|scope| {
+            for slot in &self.workers {
+                scope.spawn(|| {
+                    let mut slot = slot.lock().unwrap_or_else(|err| err.into_inner());
+
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&tile) = tiles.get(index) else {
+                            break;
+                        };
+                        let mut request = Request {
+                            film: Film::with_bounds(tile),
+                            pass_samples,
+                            max_samples,
+                        };
+                        request.film.merge(film);
+
+                        let result = match slot
+                            .as_mut()
+                            .map(|worker| worker.render(&request, film.bounds()))
+                        {
+                            Some(Ok(result)) => result,
+                            Some(Err(err)) => {
+                                eprintln!("worker failed, rendering its tiles here: {err}");
+                                if let Some(worker) = slot.take() {
+                                    worker.close();
+                                }
+                                render_local(tile)
+                            }
+                            None => render_local(tile),
+                        };
+
+                        if let Some(result) = result {
+                            on_tile(&result);
+                            rendered.lock().unwrap().push((index, result));
+                        }
+                    }
+                });
+            }
+        });
+
+        rendered.into_inner().unwrap()
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        for slot in &mut self.workers {
+            let slot = slot.get_mut().unwrap_or_else(|err| err.into_inner());
+            if let Some(worker) = slot.take() {
+                worker.close();
+            }
+        }
+    }
+}
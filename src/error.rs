@@ -0,0 +1,59 @@
+use std::{fmt, io, path::PathBuf};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The file could not be opened or read.
+    Io { path: PathBuf, source: io::Error },
+    /// The file was read, but its contents could not be decoded.
+    Decode {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// The file is not in an image format we can decode.
+    UnsupportedFormat { path: PathBuf },
+    /// The file could not be created or written.
+    Write { path: PathBuf, source: io::Error },
+    /// The file is not a checkpoint, or not one of the render resuming from it.
+    InvalidCheckpoint { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "could not read {}: {source}", path.display()),
+            Error::Decode { path, source } => {
+                write!(f, "could not decode {}: {source}", path.display())
+            }
+            Error::UnsupportedFormat { path } => {
+                write!(f, "unsupported image format: {}", path.display())
+            }
+            Error::Write { path, source } => {
+                write!(f, "could not write {}: {source}", path.display())
+            }
+            Error::InvalidCheckpoint { path, reason } => {
+                write!(f, "invalid checkpoint {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Write { source, .. } => Some(source),
+            Error::Decode { source, .. } => Some(source),
+            Error::UnsupportedFormat { .. } | Error::InvalidCheckpoint { .. } => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Io { source, .. } | Error::Write { source, .. } => source,
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::{self, Seek, Write};
+
+use image::{DynamicImage, ImageOutputFormat, Rgb32FImage};
+
+use crate::{
+    aov::{Aov, AovPixel},
+    filter::PixelFilter,
+    interval::Interval,
+    texture::ColorRamp,
+    tile::Rect,
+    vector::{Color, Vector3},
+};
+
+/// Total filter weight below which `Pixel::color` stops trusting the weighted sum.
+const MIN_WEIGHT_SUM: f32 = 1e-3;
+
+/// Running statistics of the samples taken for one pixel, along with the
+/// filter-weighted contributions of every sample near enough to count towards it
+/// and the AOVs of the samples taken in it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pixel {
+    pub(crate) sum: Color,
+    pub(crate) luminance_squares: f32,
+    pub(crate) samples: u32,
+    pub(crate) weighted_sum: Color,
+    pub(crate) weight_sum: f32,
+    pub(crate) aovs: AovPixel,
+}
+
+impl Pixel {
+    /// Records a sample taken in this pixel, for the sample statistics.
+    pub fn add_sample(&mut self, color: Color) {
+        self.sum += color;
+        self.luminance_squares += color.luminance() * color.luminance();
+        self.samples += 1;
+    }
+
+    /// Adds a sample from this pixel or a neighbour to the reconstructed color.
+    pub fn add_weighted(&mut self, color: Color, weight: f32) {
+        self.weighted_sum += color * weight;
+        self.weight_sum += weight;
+    }
+
+    pub fn merge(&mut self, other: &Pixel) {
+        self.sum += other.sum;
+        self.luminance_squares += other.luminance_squares;
+        self.samples += other.samples;
+        self.weighted_sum += other.weighted_sum;
+        self.weight_sum += other.weight_sum;
+        self.aovs.merge(&other.aovs);
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn aovs(&self) -> &AovPixel {
+        &self.aovs
+    }
+
+    /// The pixel color reconstructed by the filter. The negative lobes of
+    /// Mitchell and Lanczos can leave a pixel with too little total weight to
+    /// divide by, in which case this falls back to the mean of the samples taken
+    /// in it, and can push channels below zero, which are clamped.
+    pub fn color(&self) -> Color {
+        if self.weight_sum <= MIN_WEIGHT_SUM {
+            return self.mean();
+        }
+
+        let color = self.weighted_sum / self.weight_sum;
+        Color::new(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0))
+    }
+
+    pub fn mean(&self) -> Color {
+        if self.samples == 0 {
+            return Color::black();
+        }
+
+        self.sum / self.samples as f32
+    }
+
+    /// Unbiased estimate of the variance of the sample luminance.
+    pub fn variance(&self) -> f32 {
+        if self.samples < 2 {
+            return 0.0;
+        }
+
+        let n = self.samples as f32;
+        let mean = self.sum.luminance() / n;
+        ((self.luminance_squares - n * mean * mean) / (n - 1.0)).max(0.0)
+    }
+
+    /// Estimated standard deviation of the mean luminance around its true value.
+    pub fn standard_error(&self) -> f32 {
+        if self.samples < 2 {
+            return f32::INFINITY;
+        }
+
+        (self.variance() / self.samples as f32).sqrt()
+    }
+
+    /// Standard error of the mean luminance relative to the mean itself. Dark pixels
+    /// are measured against a floor of `0.01` so they do not soak up samples to
+    /// resolve noise nobody can see.
+    pub fn relative_error(&self) -> f32 {
+        self.standard_error() / self.mean().luminance().max(0.01)
+    }
+}
+
+/// The image being rendered, as per-pixel sample statistics. A film can cover
+/// just part of the image, such as a crop window or a single tile; pixels are
+/// always addressed by their position in the whole image.
+#[derive(Clone, Debug)]
+pub struct Film {
+    bounds: Rect,
+    pub(crate) pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::with_bounds(Rect::new(0, 0, width, height))
+    }
+
+    pub fn with_bounds(bounds: Rect) -> Self {
+        Self {
+            bounds,
+            pixels: vec![Pixel::default(); bounds.area() as usize],
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    pub fn width(&self) -> u32 {
+        self.bounds.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.bounds.height()
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &Pixel {
+        &self.pixels[self.index(x, y)]
+    }
+
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut Pixel {
+        let index = self.index(x, y);
+        &mut self.pixels[index]
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        debug_assert!(self.bounds.contains(x, y));
+        ((y - self.bounds.y0) * self.width() + (x - self.bounds.x0)) as usize
+    }
+
+    /// Adds a sample taken in pixel `(x, y)`, `offset` pixels from its center, and
+    /// splats it into every pixel of the film within the radius of `filter`.
+    pub fn add_sample(
+        &mut self,
+        (x, y): (u32, u32),
+        offset: (f32, f32),
+        color: Color,
+        filter: &PixelFilter,
+    ) {
+        self.pixel_mut(x, y).add_sample(color);
+
+        // Every pixel whose center is within the radius of the sample.
+        let reach = (filter.radius() + 0.5).ceil() as u32;
+        let neighbours = Rect::new(
+            x.saturating_sub(reach),
+            y.saturating_sub(reach),
+            x + reach + 1,
+            y + reach + 1,
+        );
+
+        for (nx, ny) in neighbours.intersect(&self.bounds).pixels() {
+            // Offsets are taken from the integer distance between the pixels, so
+            // they stay exact however far the pixels are from the origin.
+            let dx = (x as i64 - nx as i64) as f32 + offset.0;
+            let dy = (y as i64 - ny as i64) as f32 + offset.1;
+            let weight = filter.evaluate(dx, dy);
+            if weight != 0.0 {
+                self.pixel_mut(nx, ny).add_weighted(color, weight);
+            }
+        }
+    }
+
+    /// Adds the samples of `other` to the pixels the two films share.
+    pub fn merge(&mut self, other: &Film) {
+        for (x, y) in self.bounds.intersect(&other.bounds).pixels() {
+            self.pixel_mut(x, y).merge(other.pixel(x, y));
+        }
+    }
+
+    pub fn min_samples(&self) -> u32 {
+        self.pixels.iter().map(Pixel::samples).min().unwrap_or(0)
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|pixel| pixel.samples as u64).sum()
+    }
+
+    pub fn mean_samples(&self) -> f32 {
+        self.total_samples() as f32 / self.pixels.len().max(1) as f32
+    }
+
+    /// Estimated root-mean-square error of the image luminance, from the standard
+    /// error of every pixel. Infinite until every pixel has two samples.
+    pub fn estimated_rmse(&self) -> f32 {
+        let mean_squared = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel.standard_error().powi(2))
+            .sum::<f32>()
+            / self.pixels.len().max(1) as f32;
+
+        mean_squared.sqrt()
+    }
+
+    /// Writes the reconstructed color of every pixel as a plain-text PPM.
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        self.write_colors(out, Pixel::color)
+    }
+
+    /// Writes one AOV as a 32-bit float OpenEXR image.
+    pub fn write_aov(&self, aov: Aov, out: &mut (impl Write + Seek)) -> io::Result<()> {
+        let channels = self
+            .pixels
+            .iter()
+            .flat_map(|pixel| pixel.aovs.channels(aov))
+            .collect();
+        let image = Rgb32FImage::from_raw(self.width(), self.height(), channels)
+            .expect("the film has one pixel per channel triple");
+
+        DynamicImage::ImageRgb32F(image)
+            .write_to(out, ImageOutputFormat::OpenExr)
+            .map_err(io::Error::other)
+    }
+
+    /// Writes a PPM showing how many samples each pixel took, from dark blue for
+    /// the fewest to white for `max_samples`.
+    pub fn write_sample_heatmap(&self, out: &mut impl Write, max_samples: u32) -> io::Result<()> {
+        let ramp = ColorRamp::new(vec![
+            (0.0, Color::new(0.0, 0.0, 0.2)),
+            (0.33, Color::new(0.6, 0.0, 0.6)),
+            (0.66, Color::new(1.0, 0.5, 0.0)),
+            (1.0, Color::white()),
+        ]);
+        let max_samples = max_samples.max(1) as f32;
+
+        self.write_colors(out, |pixel| {
+            ramp.color(pixel.samples() as f32 / max_samples)
+        })
+    }
+
+    fn write_colors(
+        &self,
+        out: &mut impl Write,
+        color: impl Fn(&Pixel) -> Color,
+    ) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width(), self.height())?;
+        writeln!(out, "255")?;
+
+        let intensity = Interval::new(0.0, 0.999);
+        for pixel in &self.pixels {
+            let color = color(pixel);
+            let color = Vector3::new(
+                256.0 * intensity.clamp(color.x),
+                256.0 * intensity.clamp(color.y),
+                256.0 * intensity.clamp(color.z),
+            );
+
+            writeln!(out, "{} {} {}", color.x as u8, color.y as u8, color.z as u8)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pixel;
+    use crate::{filter::PixelFilter, vector::Color};
+
+    fn rgb(color: Color) -> [f32; 3] {
+        [color.x, color.y, color.z]
+    }
+
+    #[test]
+    fn negative_lobes_fall_back_to_the_sample_mean() {
+        let filter = PixelFilter::Mitchell {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        let own = Color::new(0.2, 0.4, 0.6);
+        let neighbour = Color::white();
+
+        // One sample in the corner of the pixel, outweighed by the negative lobe
+        // of the samples around it.
+        let mut pixel = Pixel::default();
+        pixel.add_sample(own);
+        pixel.add_weighted(own, filter.evaluate(0.5, 0.5));
+        let lobe = filter.evaluate(1.4, 0.0);
+        assert!(lobe < 0.0);
+        while pixel.weight_sum > 0.0 {
+            pixel.add_weighted(neighbour, lobe);
+        }
+
+        assert_eq!(rgb(pixel.color()), rgb(own));
+    }
+
+    #[test]
+    fn negative_lobes_do_not_make_colors_negative() {
+        let mut pixel = Pixel::default();
+        pixel.add_weighted(Color::black(), 0.05);
+        pixel.add_weighted(Color::white(), -0.03);
+
+        assert_eq!(rgb(pixel.color()), [0.0; 3]);
+    }
+}
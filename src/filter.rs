@@ -0,0 +1,106 @@
+use std::f32::consts::PI;
+
+/// Reconstruction filter that weighs how much a sample contributes to the pixels
+/// around it. Every filter is separable and evaluated at the offset, in pixels,
+/// from a pixel center to the sample.
+///
+/// Wider filters blur more but alias less. Mitchell and Lanczos have negative
+/// lobes that sharpen edges, at the cost of slight ringing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFilter {
+    /// Equal weight for every sample within `radius`. With a radius of `0.5` each
+    /// sample only counts towards the pixel it was taken in.
+    Box { radius: f32 },
+    /// Weight falling linearly to zero at `radius`.
+    Tent { radius: f32 },
+    /// Gaussian with standard deviation `sigma`, shifted down to reach zero at
+    /// `radius`.
+    Gaussian { radius: f32, sigma: f32 },
+    /// Mitchell-Netravali cubic. `b = c = 1/3` is the usual compromise between
+    /// blurring and ringing.
+    Mitchell { radius: f32, b: f32, c: f32 },
+    /// Sinc windowed by a wider sinc, with `tau` lobes across the radius.
+    Lanczos { radius: f32, tau: f32 },
+}
+
+impl Default for PixelFilter {
+    fn default() -> Self {
+        PixelFilter::Box { radius: 0.5 }
+    }
+}
+
+impl PixelFilter {
+    pub fn radius(&self) -> f32 {
+        match *self {
+            PixelFilter::Box { radius }
+            | PixelFilter::Tent { radius }
+            | PixelFilter::Gaussian { radius, .. }
+            | PixelFilter::Mitchell { radius, .. }
+            | PixelFilter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// Weight of a sample at offset `(x, y)` from a pixel center.
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        match *self {
+            // Half-open, so a sample exactly between two pixels only counts
+            // towards one of them.
+            PixelFilter::Box { radius } => {
+                if (-radius..radius).contains(&x) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            PixelFilter::Tent { radius } => (radius - x.abs()).max(0.0),
+            PixelFilter::Gaussian { radius, sigma } => {
+                (gaussian(x, sigma) - gaussian(radius, sigma)).max(0.0)
+            }
+            PixelFilter::Mitchell { radius, b, c } => {
+                if x.abs() >= radius {
+                    return 0.0;
+                }
+                mitchell(2.0 * x.abs() / radius, b, c)
+            }
+            PixelFilter::Lanczos { radius, tau } => {
+                if x.abs() >= radius {
+                    return 0.0;
+                }
+                sinc(x) * sinc(x / tau)
+            }
+        }
+    }
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    (-x * x / (2.0 * sigma * sigma)).exp()
+}
+
+/// The Mitchell-Netravali cubic over `[0, 2)`.
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let x2 = x * x;
+    let x3 = x2 * x;
+
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else {
+        (-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    };
+
+    value / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+
+    (PI * x).sin() / (PI * x)
+}
@@ -1,4 +1,5 @@
 use crate::{
+    dot,
     interval::Interval,
     material::Surface,
     ray::Ray,
@@ -6,13 +7,35 @@ use crate::{
     vector::{Point, Vector3},
 };
 
+#[derive(Clone)]
 pub struct HitRecord<'a> {
     pub p: Point,
+    /// Hit point in the primitive's own frame, before any `Translate` or `RotateY`
+    /// and following the motion of moving primitives.
+    pub local_p: Point,
     pub normal: Vector3,
     pub t: f32,
     pub u: f32,
     pub v: f32,
+    /// Partial derivative of the hit point with respect to `u` (the surface tangent).
+    pub dpdu: Vector3,
+    /// Partial derivative of the hit point with respect to `v` (the surface bitangent).
+    pub dpdv: Vector3,
+    /// `dpdu` and `dpdv` in the primitive's own frame, like `local_p`.
+    pub local_dpdu: Vector3,
+    pub local_dpdv: Vector3,
+    /// Screen-space derivatives of the hit point and its texture coordinates, from
+    /// the ray differentials. All zero when the ray carries none.
+    pub dpdx: Vector3,
+    pub dpdy: Vector3,
+    pub dudx: f32,
+    pub dudy: f32,
+    pub dvdx: f32,
+    pub dvdy: f32,
     pub material: &'a Surface,
+    /// The outermost element that was hit, so a hit on a side of a translated box
+    /// reports the `Translate` around it. Set by `Element::hit`.
+    pub element: Option<&'a Element>,
     pub front_face: bool,
 }
 
@@ -28,11 +51,23 @@ impl<'a> HitRecord<'a> {
     ) -> Self {
         Self {
             p,
+            local_p: p,
             normal,
             t,
             u,
             v,
+            dpdu: Vector3::zero(),
+            dpdv: Vector3::zero(),
+            local_dpdu: Vector3::zero(),
+            local_dpdv: Vector3::zero(),
+            dpdx: Vector3::zero(),
+            dpdy: Vector3::zero(),
+            dudx: 0.0,
+            dudy: 0.0,
+            dvdx: 0.0,
+            dvdy: 0.0,
             material,
+            element: None,
             front_face,
         }
     }
@@ -47,10 +82,69 @@ impl<'a> HitRecord<'a> {
             self.normal = -(*outward_normal)
         }
     }
+
+    /// Sets the partial derivatives of the hit point with respect to `u` and `v`,
+    /// in the primitive's own frame.
+    pub fn set_tangents(&mut self, dpdu: Vector3, dpdv: Vector3) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self.local_dpdu = dpdu;
+        self.local_dpdv = dpdv;
+    }
+
+    /// Estimates how the hit point and texture coordinates change across a pixel by
+    /// intersecting the ray differentials with the tangent plane at the hit point.
+    pub fn set_differentials(&mut self, ray: &Ray) {
+        let Some(differential) = ray.differential else {
+            return;
+        };
+
+        let d = -dot(&self.normal, &self.p);
+        let offset = |origin: &Point, direction: &Vector3| {
+            let t = -(dot(&self.normal, origin) + d) / dot(&self.normal, direction);
+            if t.is_finite() {
+                *origin + *direction * t - self.p
+            } else {
+                Vector3::zero()
+            }
+        };
+        self.dpdx = offset(&differential.rx_origin, &differential.rx_direction);
+        self.dpdy = offset(&differential.ry_origin, &differential.ry_direction);
+
+        // Least-squares fit of dp/dx = du/dx * dp/du + dv/dx * dp/dv (and the same for y).
+        let ata00 = dot(&self.dpdu, &self.dpdu);
+        let ata01 = dot(&self.dpdu, &self.dpdv);
+        let ata11 = dot(&self.dpdv, &self.dpdv);
+        let inv_det = (ata00 * ata11 - ata01 * ata01).recip();
+        if !inv_det.is_finite() {
+            return;
+        }
+
+        let solve = |dp: &Vector3| {
+            let atb0 = dot(&self.dpdu, dp);
+            let atb1 = dot(&self.dpdv, dp);
+            (
+                ((ata11 * atb0 - ata01 * atb1) * inv_det).clamp(-1e8, 1e8),
+                ((ata00 * atb1 - ata01 * atb0) * inv_det).clamp(-1e8, 1e8),
+            )
+        };
+        (self.dudx, self.dvdx) = solve(&self.dpdx);
+        (self.dudy, self.dvdy) = solve(&self.dpdy);
+    }
+
+    /// Returns the geometric normal pointing out of the surface, regardless of
+    /// which side the ray hit.
+    pub fn outward_normal(&self) -> Vector3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
 }
 
 pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>>;
 }
 
 #[derive(Default)]
@@ -73,7 +167,7 @@ impl HittableList {
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let mut rec = None;
         let mut closest_so_far = ray_t.max;
 
@@ -87,3 +181,68 @@ impl Hittable for HittableList {
         rec
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HitRecord;
+    use crate::{
+        material::Surface,
+        ray::{Ray, RayDifferential},
+        vector::{Point, Vector3},
+    };
+
+    #[test]
+    fn differentials_project_onto_the_tangent_plane() {
+        let surface = Surface::default();
+        let mut rec = HitRecord::new(
+            Point::zero(),
+            Vector3::new(0.0, 0.0, 1.0),
+            1.0,
+            &surface,
+            true,
+            0.5,
+            0.5,
+        );
+        // A plane with `u` along x, two units per unit of `u`, and `v` along y.
+        rec.set_tangents(Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+
+        let origin = Point::new(0.0, 0.0, 1.0);
+        let ray = Ray::new(origin, Vector3::new(0.0, 0.0, -1.0), 0.0).with_differential(Some(
+            RayDifferential {
+                rx_origin: origin,
+                rx_direction: Vector3::new(0.1, 0.0, -1.0),
+                ry_origin: origin,
+                ry_direction: Vector3::new(0.0, -0.2, -1.0),
+            },
+        ));
+        rec.set_differentials(&ray);
+
+        assert!((rec.dpdx - Vector3::new(0.1, 0.0, 0.0)).length() < 1e-6);
+        assert!((rec.dpdy - Vector3::new(0.0, -0.2, 0.0)).length() < 1e-6);
+        assert!((rec.dudx - 0.05).abs() < 1e-6 && rec.dvdx.abs() < 1e-6);
+        assert!(rec.dudy.abs() < 1e-6 && (rec.dvdy + 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rays_without_differentials_leave_the_footprint_empty() {
+        let surface = Surface::default();
+        let mut rec = HitRecord::new(
+            Point::zero(),
+            Vector3::new(0.0, 0.0, 1.0),
+            1.0,
+            &surface,
+            true,
+            0.5,
+            0.5,
+        );
+        rec.set_tangents(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        rec.set_differentials(&Ray::new(
+            Point::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        ));
+
+        assert_eq!(rec.dpdx.length(), 0.0);
+        assert_eq!([rec.dudx, rec.dudy, rec.dvdx, rec.dvdy], [0.0; 4]);
+    }
+}
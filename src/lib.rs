@@ -1,24 +1,46 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use vector::Vector3;
 
+pub mod aov;
 pub mod camera;
+pub mod checkpoint;
+pub mod denoise;
+pub mod distributed;
+pub mod error;
+pub mod film;
+pub mod filter;
 pub mod hittable;
 pub mod interval;
 pub mod material;
 pub mod noise;
 pub mod ray;
+pub mod sampler;
 pub mod shape;
+#[cfg(test)]
+mod test_scene;
 pub mod texture;
+pub mod tile;
 pub mod vector;
 
 pub fn dot(fst: &Vector3, snd: &Vector3) -> f32 {
     fst.dot(snd)
 }
 
-pub fn random_rng(min: f32, max: f32) -> f32 {
-    let mut rng = rand::thread_rng();
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
 
-    rng.gen_range(min..=max)
+/// Reseeds the generator behind `random`, `random_rng` and `random_int_rng` on the
+/// current thread. Each thread has its own generator, so this only makes the calls
+/// that follow on the same thread reproducible.
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+pub fn random_rng(min: f32, max: f32) -> f32 {
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..=max))
 }
 
 #[inline]
@@ -27,9 +49,7 @@ pub fn random() -> f32 {
 }
 
 pub fn random_int_rng(min: i32, max: i32) -> i32 {
-    let mut rng = rand::thread_rng();
-
-    rng.gen_range(min..=max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..=max))
 }
 
 #[inline]
@@ -50,7 +70,7 @@ pub fn lerp(t: f32, a: f32, b: f32) -> f32 {
     a + t * (b - a)
 }
 
-pub fn grad(hash: i32, x: f32, y: f32, z: f32) -> f32 {
+pub fn grad(hash: u8, x: f32, y: f32, z: f32) -> f32 {
     let h = hash & 15;
 
     let u = if h < 8 { x } else { y };
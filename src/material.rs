@@ -1,27 +1,176 @@
+use std::f32::consts::PI;
+
 use crate::{
     dot,
-    hittable::HitRecord,
-    random,
+    hittable::{HitRecord, Hittable},
+    lerp, random,
     ray::Ray,
     reflect_ray, refract_ray,
-    texture::Texture,
-    vector::{Color, Point, Vector3},
+    sampler::Sampler,
+    shape::Element,
+    texture::{TexCoord, Texture},
+    vector::{Color, Vector3},
 };
 
 pub trait Material: Sync {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)>;
     #[allow(unused_variables)]
-    fn emitted(&self, u: f32, v: f32, point: &Point) -> Color {
+    fn emitted(&self, coord: &TexCoord) -> Color {
         Color::black()
     }
+    #[allow(unused_variables)]
+    fn opacity(&self, coord: &TexCoord) -> f32 {
+        1.0
+    }
+
+    /// Decides whether a hit at the given point is kept. Partially opaque points
+    /// are kept with a probability equal to their opacity.
+    fn alpha_test(&self, coord: &TexCoord) -> bool {
+        let opacity = self.opacity(coord);
+        opacity >= 1.0 || (opacity > 0.0 && random() < opacity)
+    }
+
+    /// The color the surface reflects at the hit, ignoring lighting, for the
+    /// albedo AOV.
+    #[allow(unused_variables)]
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        Color::white()
+    }
+
+    /// The normal the surface is shaded with at the hit, for the normal AOV.
+    fn shading_normal(&self, rec: &HitRecord) -> Vector3 {
+        rec.normal
+    }
 }
 
 #[derive(Clone)]
 pub enum Surface {
-    Diffuse { albedo: Texture },
-    Reflective { albedo: Color, fuzz: f32 },
-    Refractive { idx_of_refraction: f32 },
+    Diffuse {
+        albedo: Texture,
+    },
+    /// Rough diffuse surface. `roughness` is the standard deviation of the
+    /// microfacet slope angle, in degrees; `0.0` matches `Diffuse`.
+    OrenNayar {
+        albedo: Texture,
+        roughness: f32,
+    },
+    /// Diffuse base with a Charlie sheen lobe on top, for cloth and velvet.
+    /// `roughness` is in `[0, 1]`; higher values spread the sheen wider.
+    Sheen {
+        albedo: Texture,
+        sheen: Color,
+        roughness: f32,
+    },
+    Reflective {
+        albedo: Color,
+        fuzz: f32,
+    },
+    Refractive {
+        idx_of_refraction: f32,
+    },
+    /// Random-walk subsurface scattering inside the closed geometry the surface is
+    /// assigned to. `albedo` is the single-scattering albedo and `mean_free_path`
+    /// the average distance between scattering events, both per color channel.
+    /// Every channel of `mean_free_path` must be greater than zero. The walk
+    /// runs inside the material against the element that was hit, so it costs a
+    /// single bounce of the camera's `max_depth` however often it scatters.
+    Subsurface {
+        albedo: Color,
+        mean_free_path: Color,
+        idx_of_refraction: f32,
+    },
     DiffuseLight(Texture),
+    /// Wraps another surface and perturbs its shading normal with `map`.
+    Bumped {
+        surface: Box<Surface>,
+        map: NormalMap,
+    },
+    /// Wraps another surface with an opacity mask. Intersections are skipped where
+    /// the mask is transparent, so single quads can stand in for leaves or fences.
+    Cutout {
+        surface: Box<Surface>,
+        opacity: Texture,
+    },
+    /// Blends two surfaces by the scalar value of `weight`: `0.0` is all `first`,
+    /// `1.0` is all `second`. Each scatter picks one of them at random.
+    Mix {
+        first: Box<Surface>,
+        second: Box<Surface>,
+        weight: Texture,
+    },
+}
+
+#[derive(Clone)]
+pub enum NormalMap {
+    /// Tangent-space normal map whose RGB channels encode XYZ remapped to `[0, 1]`.
+    /// Image normal maps should be decoded with `ColorSpace::Linear`.
+    Tangent(Texture),
+    /// Scalar height field, displaced along the normal by `scale` world units.
+    Bump { height: Texture, scale: f32 },
+}
+
+impl NormalMap {
+    /// Returns the perturbed normal, facing the same side as `rec.normal`.
+    pub fn shading_normal(&self, rec: &HitRecord) -> Vector3 {
+        let normal = rec.outward_normal();
+
+        let perturbed = match self {
+            NormalMap::Tangent(texture) => {
+                let color = texture.value(&TexCoord::from(rec));
+                let (tangent, bitangent) = tangent_frame(&normal, &rec.dpdu);
+
+                tangent * (2.0 * color.x - 1.0)
+                    + bitangent * (2.0 * color.y - 1.0)
+                    + normal * (2.0 * color.z - 1.0)
+            }
+            NormalMap::Bump { height, scale } => {
+                // Finite differences of the displaced surface along u and v.
+                let delta = 0.0005;
+                let coord = TexCoord::from(rec);
+                let u_coord = TexCoord {
+                    u: rec.u + delta,
+                    p: rec.p + rec.dpdu * delta,
+                    local_p: rec.local_p + rec.local_dpdu * delta,
+                    ..coord
+                };
+                let v_coord = TexCoord {
+                    v: rec.v + delta,
+                    p: rec.p + rec.dpdv * delta,
+                    local_p: rec.local_p + rec.local_dpdv * delta,
+                    ..coord
+                };
+                let displace = scale * height.scalar_value(&coord);
+                let u_displace = scale * height.scalar_value(&u_coord);
+                let v_displace = scale * height.scalar_value(&v_coord);
+
+                let dpdu = rec.dpdu + normal * ((u_displace - displace) / delta);
+                let dpdv = rec.dpdv + normal * ((v_displace - displace) / delta);
+                let bumped = dpdu.cross(&dpdv);
+
+                if dot(&bumped, &normal) < 0.0 {
+                    -bumped
+                } else {
+                    bumped
+                }
+            }
+        };
+
+        if perturbed.length_squared() < 1e-12 {
+            return rec.normal;
+        }
+
+        let perturbed = perturbed.unit();
+        if rec.front_face {
+            perturbed
+        } else {
+            -perturbed
+        }
+    }
 }
 
 impl Default for Surface {
@@ -33,24 +182,83 @@ impl Default for Surface {
 }
 
 impl Material for Surface {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
         match self {
             Surface::Diffuse { albedo } => {
-                let mut scatter_direction = rec.normal + Vector3::random_unit_vector();
+                let mut scatter_direction =
+                    rec.normal + Vector3::sample_unit_sphere(sampler.get_2d());
+                if scatter_direction.near_zero() {
+                    scatter_direction = rec.normal;
+                }
+
+                let scattered = Ray::new(rec.p, scatter_direction, ray_in.time);
+                Some((scattered, albedo.value(&TexCoord::from(rec))))
+            }
+            Surface::OrenNayar { albedo, roughness } => {
+                let mut scatter_direction =
+                    rec.normal + Vector3::sample_unit_sphere(sampler.get_2d());
+                if scatter_direction.near_zero() {
+                    scatter_direction = rec.normal;
+                }
+
+                // The cosine-weighted direction cancels the Lambertian term, leaving
+                // only the Oren-Nayar factor as the attenuation.
+                let factor = oren_nayar(
+                    &-ray_in.direction.unit(),
+                    &scatter_direction.unit(),
+                    &rec.normal,
+                    roughness.to_radians(),
+                );
+
+                let scattered = Ray::new(rec.p, scatter_direction, ray_in.time);
+                Some((scattered, albedo.value(&TexCoord::from(rec)) * factor))
+            }
+            Surface::Sheen {
+                albedo,
+                sheen,
+                roughness,
+            } => {
+                let mut scatter_direction =
+                    rec.normal + Vector3::sample_unit_sphere(sampler.get_2d());
                 if scatter_direction.near_zero() {
                     scatter_direction = rec.normal;
                 }
 
+                let wo = -ray_in.direction.unit();
+                let wi = scatter_direction.unit();
+                let n_dot_l = dot(&rec.normal, &wi).max(0.0);
+                let n_dot_v = dot(&rec.normal, &wo).max(0.0);
+                let half = (wi + wo).unit();
+
+                // The sheen lobe is divided by the cosine-weighted pdf (cos / PI).
+                let lobe = charlie_distribution(dot(&rec.normal, &half), *roughness)
+                    * neubelt_visibility(n_dot_l, n_dot_v)
+                    * PI;
+
                 let scattered = Ray::new(rec.p, scatter_direction, ray_in.time);
-                Some((scattered, albedo.color(rec.u, rec.v, &rec.p)))
+                Some((
+                    scattered,
+                    albedo.value(&TexCoord::from(rec)) + *sheen * lobe,
+                ))
             }
             Surface::Reflective { albedo, fuzz } => {
                 let reflected = reflect_ray(&ray_in.direction, &rec.normal);
+                let differential = ray_in.differential.map(|d| {
+                    d.scattered(&rec.p, &rec.dpdx, &rec.dpdy, |dir| {
+                        reflect_ray(dir, &rec.normal)
+                    })
+                });
                 let scattered = Ray::new(
                     rec.p,
-                    reflected + Vector3::random_unit_vector() * *fuzz,
+                    reflected + Vector3::sample_unit_sphere(sampler.get_2d()) * *fuzz,
                     ray_in.time,
-                );
+                )
+                .with_differential(differential);
 
                 if dot(&scattered.direction, &rec.normal) > 0.0 {
                     Some((scattered, *albedo))
@@ -59,57 +267,595 @@ impl Material for Surface {
                 }
             }
             Surface::Refractive { idx_of_refraction } => {
+                let direction = dielectric_direction(
+                    &ray_in.direction,
+                    rec,
+                    *idx_of_refraction,
+                    sampler.get_1d(),
+                );
+
+                // Bend the differentials the same way the main ray went: reflected rays
+                // leave on the side of the normal, refracted ones on the other side.
                 let refraction_ratio = if rec.front_face {
                     1.0 / idx_of_refraction
                 } else {
                     *idx_of_refraction
                 };
+                let reflected = dot(&direction, &rec.normal) > 0.0;
+                let differential = ray_in.differential.map(|d| {
+                    d.scattered(&rec.p, &rec.dpdx, &rec.dpdy, |dir| {
+                        if reflected {
+                            reflect_ray(dir, &rec.normal)
+                        } else {
+                            refract_ray(&dir.unit(), &rec.normal, refraction_ratio)
+                        }
+                    })
+                });
+                let scattered =
+                    Ray::new(rec.p, direction, ray_in.time).with_differential(differential);
 
-                let unit_direction = ray_in.direction.unit();
-                let cos_theta = f32::min(dot(&-unit_direction, &rec.normal), 1.0);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-
-                let cannot_refract = refraction_ratio * sin_theta > 1.0;
+                Some((scattered, Color::white()))
+            }
+            Surface::Subsurface {
+                albedo,
+                mean_free_path,
+                idx_of_refraction,
+            } => {
+                let medium = Medium::new(*albedo, *mean_free_path, *idx_of_refraction);
 
-                let direction =
-                    if cannot_refract || reflectance(cos_theta, refraction_ratio) > random() {
-                        reflect_ray(&unit_direction, &rec.normal)
-                    } else {
-                        refract_ray(&unit_direction, &rec.normal, refraction_ratio)
+                // Entering the object: the boundary behaves like a dielectric.
+                if rec.front_face {
+                    let direction = dielectric_direction(
+                        &ray_in.direction,
+                        rec,
+                        *idx_of_refraction,
+                        sampler.get_1d(),
+                    );
+                    let refracted = Ray::new(rec.p, direction, ray_in.time);
+                    return match rec.element {
+                        Some(element) if dot(&direction, &rec.normal) < 0.0 => {
+                            medium.random_walk(element, refracted, sampler)
+                        }
+                        _ => Some((refracted, Color::white())),
                     };
+                }
 
-                let scattered = Ray::new(rec.p, direction, ray_in.time);
-
-                Some((scattered, Color::white()))
+                // Hitting a back face without having walked in, as when the camera
+                // starts inside the object: the ray travelled through the medium
+                // from its origin.
+                let (scattered, weight, _) = medium.free_flight(ray_in, rec, sampler);
+                Some((scattered, weight))
             }
             Surface::DiffuseLight(_) => None,
+            Surface::Bumped { surface, map } => {
+                let mut shaded = rec.clone();
+                shaded.normal = map.shading_normal(rec);
+
+                surface.scatter(ray_in, &shaded, sampler)
+            }
+            Surface::Cutout { surface, .. } => surface.scatter(ray_in, rec, sampler),
+            Surface::Mix {
+                first,
+                second,
+                weight,
+            } => {
+                if sampler.get_1d() < mix_weight(weight, &TexCoord::from(rec)) {
+                    second.scatter(ray_in, rec, sampler)
+                } else {
+                    first.scatter(ray_in, rec, sampler)
+                }
+            }
         }
     }
 
-    fn emitted(&self, u: f32, v: f32, point: &Point) -> Color {
+    fn emitted(&self, coord: &TexCoord) -> Color {
         match self {
-            Surface::DiffuseLight(emit) => emit.color(u, v, point),
+            Surface::DiffuseLight(emit) => emit.value(coord),
+            Surface::Bumped { surface, .. } | Surface::Cutout { surface, .. } => {
+                surface.emitted(coord)
+            }
+            Surface::Mix {
+                first,
+                second,
+                weight,
+            } => {
+                let t = mix_weight(weight, coord);
+                first.emitted(coord) * (1.0 - t) + second.emitted(coord) * t
+            }
             _ => Color::black(),
         }
     }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        match self {
+            Surface::Diffuse { albedo }
+            | Surface::OrenNayar { albedo, .. }
+            | Surface::Sheen { albedo, .. } => albedo.value(&TexCoord::from(rec)),
+            Surface::Reflective { albedo, .. } | Surface::Subsurface { albedo, .. } => *albedo,
+            Surface::Refractive { .. } => Color::white(),
+            // Lights are bright well beyond `1.0`, which would swamp the albedo
+            // of everything else.
+            Surface::DiffuseLight(emit) => {
+                let color = emit.value(&TexCoord::from(rec));
+                Color::new(
+                    color.x.clamp(0.0, 1.0),
+                    color.y.clamp(0.0, 1.0),
+                    color.z.clamp(0.0, 1.0),
+                )
+            }
+            Surface::Bumped { surface, .. } | Surface::Cutout { surface, .. } => {
+                surface.albedo(rec)
+            }
+            Surface::Mix {
+                first,
+                second,
+                weight,
+            } => {
+                let t = mix_weight(weight, &TexCoord::from(rec));
+                first.albedo(rec) * (1.0 - t) + second.albedo(rec) * t
+            }
+        }
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vector3 {
+        match self {
+            Surface::Bumped { map, .. } => map.shading_normal(rec),
+            Surface::Cutout { surface, .. } => surface.shading_normal(rec),
+            Surface::Mix {
+                first,
+                second,
+                weight,
+            } => {
+                let t = mix_weight(weight, &TexCoord::from(rec));
+                let normal = first.shading_normal(rec) * (1.0 - t) + second.shading_normal(rec) * t;
+                if normal.near_zero() {
+                    rec.normal
+                } else {
+                    normal.unit()
+                }
+            }
+            _ => rec.normal,
+        }
+    }
+
+    fn opacity(&self, coord: &TexCoord) -> f32 {
+        match self {
+            Surface::Bumped { surface, .. } => surface.opacity(coord),
+            Surface::Cutout { surface, opacity } => {
+                opacity.alpha_value(coord).clamp(0.0, 1.0) * surface.opacity(coord)
+            }
+            Surface::Mix {
+                first,
+                second,
+                weight,
+            } => lerp(
+                mix_weight(weight, coord),
+                first.opacity(coord),
+                second.opacity(coord),
+            ),
+            _ => 1.0,
+        }
+    }
+}
+
+fn mix_weight(weight: &Texture, coord: &TexCoord) -> f32 {
+    weight.scalar_value(coord).clamp(0.0, 1.0)
+}
+
+/// Builds an orthonormal tangent and bitangent around `normal`, aligning the
+/// tangent with `dpdu` when it is usable.
+fn tangent_frame(normal: &Vector3, dpdu: &Vector3) -> (Vector3, Vector3) {
+    let mut tangent = dpdu - normal * dot(normal, dpdu);
+    if tangent.length_squared() < 1e-12 {
+        let axis = if normal.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        tangent = axis.cross(normal);
+    }
+    let tangent = tangent.unit();
+
+    (tangent, normal.cross(&tangent))
+}
+
+/// Picks between reflection and refraction at a dielectric boundary, weighting the
+/// choice by the Fresnel reflectance. `u` is a sample in `[0, 1)` driving the choice.
+fn dielectric_direction(
+    direction: &Vector3,
+    rec: &HitRecord,
+    idx_of_refraction: f32,
+    u: f32,
+) -> Vector3 {
+    let refraction_ratio = if rec.front_face {
+        1.0 / idx_of_refraction
+    } else {
+        idx_of_refraction
+    };
+
+    let unit_direction = direction.unit();
+    let cos_theta = f32::min(dot(&-unit_direction, &rec.normal), 1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let cannot_refract = refraction_ratio * sin_theta > 1.0;
+
+    if cannot_refract || reflectance(cos_theta, refraction_ratio) > u {
+        reflect_ray(&unit_direction, &rec.normal)
+    } else {
+        refract_ray(&unit_direction, &rec.normal, refraction_ratio)
+    }
 }
 
-struct DiffuseLight {
-    pub emit: Texture,
+/// Most scattering events a subsurface random walk takes before the path is
+/// given up as absorbed.
+const MAX_WALK_STEPS: u32 = 1024;
+
+/// The scattering medium inside a `Surface::Subsurface`.
+struct Medium {
+    albedo: Color,
+    sigma_t: Color,
+    idx_of_refraction: f32,
 }
 
-impl Material for DiffuseLight {
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Color)> {
+impl Medium {
+    fn new(albedo: Color, mean_free_path: Color, idx_of_refraction: f32) -> Self {
+        Self {
+            albedo,
+            sigma_t: Color::new(
+                mean_free_path.x.recip(),
+                mean_free_path.y.recip(),
+                mean_free_path.z.recip(),
+            ),
+            idx_of_refraction,
+        }
+    }
+
+    /// Follows `ray` from where it entered `element` until it leaves it again,
+    /// returning the ray leaving the element and the throughput of the walk.
+    fn random_walk(
+        &self,
+        element: &Element,
+        mut ray: Ray,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let mut throughput = Color::white();
+
+        for _ in 0..MAX_WALK_STEPS {
+            let Some(rec) = element.hit(&ray, &(0.001, f32::MAX).into()) else {
+                // Only rounding lets the walk slip out without crossing the surface.
+                return Some((ray, throughput));
+            };
+
+            let (next, weight, left) = self.free_flight(&ray, &rec, sampler);
+            throughput = throughput * weight;
+            if left {
+                return Some((next, throughput));
+            }
+            ray = next;
+        }
+
         None
     }
 
-    fn emitted(&self, u: f32, v: f32, p: &Point) -> Color {
-        self.emit.color(u, v, p)
+    /// Samples a free-flight distance along `ray`, which would leave the medium
+    /// at `rec`. Returns the next ray, its weight, and whether it left the
+    /// medium. A channel is picked at random to sample with and the weight is
+    /// divided by the pdf averaged over all three channels.
+    fn free_flight(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> (Ray, Color, bool) {
+        let sigma_t = &self.sigma_t;
+        let segment = rec.t * ray.direction.length();
+        let channel_sigma = match (sampler.get_1d() * 3.0) as u32 {
+            0 => sigma_t.x,
+            1 => sigma_t.y,
+            _ => sigma_t.z,
+        };
+        let distance = -(1.0 - sampler.get_1d()).ln() / channel_sigma;
+
+        if distance < segment {
+            let transmittance = transmittance(sigma_t, distance);
+            let pdf = average(&(*sigma_t * transmittance));
+            let p = ray.origin + ray.direction.unit() * distance;
+            let scattered = Ray::new(p, Vector3::sample_unit_sphere(sampler.get_2d()), ray.time);
+
+            (
+                scattered,
+                self.albedo * *sigma_t * transmittance / pdf,
+                false,
+            )
+        } else {
+            let transmittance = transmittance(sigma_t, segment);
+            let pdf = average(&transmittance);
+            let direction = dielectric_direction(
+                &ray.direction,
+                rec,
+                self.idx_of_refraction,
+                sampler.get_1d(),
+            );
+            // The normal faces the ray, so leaving means crossing to its far side.
+            let left = dot(&direction, &rec.normal) < 0.0;
+
+            (
+                Ray::new(rec.p, direction, ray.time),
+                transmittance / pdf,
+                left,
+            )
+        }
     }
 }
 
+fn transmittance(sigma_t: &Color, distance: f32) -> Color {
+    Color::new(
+        (-sigma_t.x * distance).exp(),
+        (-sigma_t.y * distance).exp(),
+        (-sigma_t.z * distance).exp(),
+    )
+}
+
+fn average(color: &Color) -> f32 {
+    (color.x + color.y + color.z) / 3.0
+}
+
 fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
+
+/// Oren-Nayar factor for a cosine-sampled direction `wi` seen from `wo`.
+/// `sigma` is the roughness in radians.
+fn oren_nayar(wo: &Vector3, wi: &Vector3, normal: &Vector3, sigma: f32) -> f32 {
+    let sigma2 = sigma * sigma;
+    let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+    let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+    let cos_i = dot(wi, normal).clamp(0.0, 1.0);
+    let cos_o = dot(wo, normal).clamp(0.0, 1.0);
+    let sin_i = (1.0 - cos_i * cos_i).sqrt();
+    let sin_o = (1.0 - cos_o * cos_o).sqrt();
+
+    // Cosine of the azimuthal difference, from the projections on the tangent plane.
+    let wi_tangent = wi - normal * cos_i;
+    let wo_tangent = wo - normal * cos_o;
+    let max_cos = if wi_tangent.length_squared() < 1e-8 || wo_tangent.length_squared() < 1e-8 {
+        0.0
+    } else {
+        dot(&wi_tangent.unit(), &wo_tangent.unit()).max(0.0)
+    };
+
+    // alpha = max(theta_i, theta_o), beta = min(theta_i, theta_o)
+    let (sin_alpha, tan_beta) = if cos_i > cos_o {
+        (sin_o, sin_i / cos_i)
+    } else {
+        (sin_i, sin_o / cos_o.max(1e-4))
+    };
+
+    a + b * max_cos * sin_alpha * tan_beta
+}
+
+/// Charlie sheen microfacet distribution (Estevez and Kulla).
+fn charlie_distribution(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = (roughness * roughness).max(1e-3);
+    let inv_alpha = alpha.recip();
+    let sin2_h = (1.0 - n_dot_h * n_dot_h).max(0.0);
+
+    (2.0 + inv_alpha) * sin2_h.powf(inv_alpha * 0.5) / (2.0 * PI)
+}
+
+/// Neubelt and Pettineo visibility term used alongside the Charlie distribution.
+fn neubelt_visibility(n_dot_l: f32, n_dot_v: f32) -> f32 {
+    (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v))
+        .max(1e-4)
+        .recip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tangent_frame, Material, NormalMap, Surface};
+    use crate::{
+        camera::Camera,
+        dot,
+        hittable::{HitRecord, Hittable, HittableList},
+        interval::Interval,
+        noise::perlin::Perlin,
+        ray::Ray,
+        sampler::{IndependentSampler, Sampler},
+        shape::{Element, Sphere},
+        texture::{TexCoord, Texture},
+        vector::{Color, Point, Vector3},
+    };
+
+    /// Mixes a red diffuse surface into a light by `weight`.
+    fn diffuse_and_light(weight: f32) -> Surface {
+        Surface::Mix {
+            first: Box::new(Surface::Diffuse {
+                albedo: Texture::SolidColor(Color::new(1.0, 0.0, 0.0)),
+            }),
+            second: Box::new(Surface::DiffuseLight(Texture::SolidColor(Color::new(
+                0.0, 0.0, 4.0,
+            )))),
+            weight: Texture::SolidColor(Color::from_one(weight)),
+        }
+    }
+
+    /// A hit on the xy plane, seen from +z, with `u` along x and `v` along y.
+    fn plane_hit(surface: &Surface, front_face: bool) -> HitRecord<'_> {
+        let normal = Vector3::new(0.0, 0.0, if front_face { 1.0 } else { -1.0 });
+        let mut rec = HitRecord::new(Point::zero(), normal, 1.0, surface, front_face, 0.5, 0.5);
+        rec.set_tangents(Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0));
+        rec
+    }
+
+    fn assert_close(actual: Vector3, expected: Vector3) {
+        assert!(
+            (actual - expected).length() < 1e-4,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn mixes_blend_albedo_and_emission_by_weight() {
+        let coord = TexCoord::new(0.5, 0.5, Point::zero());
+        let cases = [(0.25, 0.25), (-1.0, 0.0), (3.0, 1.0)];
+
+        for (weight, clamped) in cases {
+            let surface = diffuse_and_light(weight);
+            let rec = plane_hit(&surface, true);
+
+            let albedo = surface.albedo(&rec);
+            assert!(
+                (albedo.x - (1.0 - clamped)).abs() < 1e-6,
+                "albedo {albedo:?}"
+            );
+            assert!((albedo.z - clamped).abs() < 1e-6, "albedo {albedo:?}");
+            let emitted = surface.emitted(&coord);
+            assert!(
+                (emitted.z - 4.0 * clamped).abs() < 1e-6,
+                "emitted {emitted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn mixes_scatter_from_each_surface_by_weight() {
+        let surface = diffuse_and_light(0.25);
+        let rec = plane_hit(&surface, true);
+        let ray = Ray::new(Point::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0), 0.0);
+        let mut sampler = IndependentSampler::new(5);
+
+        let count = 4000;
+        let absorbed = (0..count)
+            .filter(|&i| {
+                sampler.start_pixel_sample((0, 0), i);
+                surface.scatter(&ray, &rec, &mut sampler).is_none()
+            })
+            .count();
+
+        // Lights do not scatter, so a quarter of the rays should end at the light.
+        let fraction = absorbed as f32 / count as f32;
+        assert!(
+            (fraction - 0.25).abs() < 0.03,
+            "{fraction} of rays absorbed"
+        );
+    }
+
+    #[test]
+    fn flat_maps_keep_the_geometric_normal() {
+        let surface = Surface::default();
+        let flat = NormalMap::Tangent(Texture::SolidColor(Color::new(0.5, 0.5, 1.0)));
+        let level = NormalMap::Bump {
+            height: Texture::SolidColor(Color::from_one(0.3)),
+            scale: 2.0,
+        };
+
+        for front_face in [true, false] {
+            let rec = plane_hit(&surface, front_face);
+            assert_close(flat.shading_normal(&rec), rec.normal);
+            assert_close(level.shading_normal(&rec), rec.normal);
+        }
+    }
+
+    #[test]
+    fn normal_maps_follow_the_tangent_frame() {
+        let surface = Surface::default();
+        let along_u = NormalMap::Tangent(Texture::SolidColor(Color::new(1.0, 0.5, 0.5)));
+        let along_v = NormalMap::Tangent(Texture::SolidColor(Color::new(0.5, 1.0, 0.5)));
+
+        let front = plane_hit(&surface, true);
+        assert_close(along_u.shading_normal(&front), Vector3::new(1.0, 0.0, 0.0));
+        assert_close(along_v.shading_normal(&front), Vector3::new(0.0, 1.0, 0.0));
+
+        // From behind, the perturbed normal flips to the side of the ray.
+        let back = plane_hit(&surface, false);
+        assert_close(along_u.shading_normal(&back), Vector3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn tangent_frames_are_orthonormal_without_a_usable_dpdu() {
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        for dpdu in [Vector3::zero(), normal * 3.0, Vector3::new(1.0, 1.0, 0.0)] {
+            let (tangent, bitangent) = tangent_frame(&normal, &dpdu);
+
+            assert!((tangent.length() - 1.0).abs() < 1e-5);
+            assert!((bitangent.length() - 1.0).abs() < 1e-5);
+            assert!(dot(&tangent, &normal).abs() < 1e-5);
+            assert!(dot(&bitangent, &normal).abs() < 1e-5);
+            assert!(dot(&tangent, &bitangent).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn bumped_normals_face_the_ray_on_spheres() {
+        let sphere = Sphere::new(
+            Point::zero(),
+            1.0,
+            Surface::Bumped {
+                surface: Box::default(),
+                map: NormalMap::Bump {
+                    height: Texture::Perlin(Perlin::new(7)),
+                    scale: 0.2,
+                },
+            },
+        );
+
+        let rays = [
+            Ray::new(Point::new(0.3, 0.2, 5.0), Vector3::new(0.0, 0.0, -1.0), 0.0),
+            Ray::new(Point::zero(), Vector3::new(0.4, -0.3, 1.0), 0.0),
+        ];
+        for ray in rays {
+            let rec = sphere
+                .hit(&ray, &Interval::new(0.001, f32::INFINITY))
+                .expect("the ray hits the sphere");
+            assert!(dot(&rec.dpdu, &rec.normal).abs() < 1e-4);
+            assert!(dot(&rec.dpdv, &rec.normal).abs() < 1e-4);
+
+            let normal = rec.material.shading_normal(&rec);
+            assert!((normal.length() - 1.0).abs() < 1e-4);
+            assert!(dot(&normal, &rec.normal) > 0.0);
+            assert!(dot(&normal, &ray.direction) < 0.0);
+        }
+    }
+
+    #[test]
+    fn subsurface_walk_conserves_energy_in_a_white_furnace() {
+        let mut world = HittableList::default();
+        world.add(Element::Sphere(Sphere::new(
+            Point::zero(),
+            1.0,
+            Surface::Subsurface {
+                albedo: Color::white(),
+                mean_free_path: Color::from_one(0.1),
+                idx_of_refraction: 1.3,
+            },
+        )));
+        // The sphere fills the view, and every ray that leaves it sees a white
+        // background. Few bounces are enough, since the walk takes only one.
+        let camera = Camera::new(
+            1.0,
+            16,
+            16,
+            4,
+            20.0,
+            Point::new(0.0, 0.0, 5.0),
+            Point::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            0.0,
+            5.0,
+            Color::white(),
+        );
+
+        let film = camera.render_film(&world);
+        let mean = film
+            .pixels
+            .iter()
+            .map(|pixel| pixel.color().luminance())
+            .sum::<f32>()
+            / film.pixels.len() as f32;
+
+        assert!((mean - 1.0).abs() < 0.02, "furnace returned {mean}");
+    }
+}
@@ -0,0 +1,125 @@
+use crate::vector::Point;
+
+use super::Noise;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FractalKind {
+    /// Fractal Brownian motion: a plain sum of octaves.
+    #[default]
+    Fbm,
+    /// Ridged multifractal: sharp creases where the basis crosses zero, with each
+    /// octave weighted by the one before it.
+    Ridged,
+}
+
+/// Sums `octaves` copies of a basis noise, each one `lacunarity` times higher in
+/// frequency and `gain` times lower in amplitude than the previous. The result is
+/// normalized by the total amplitude, so it keeps the range of the basis.
+#[derive(Clone)]
+pub struct Fractal {
+    pub basis: Noise,
+    pub kind: FractalKind,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Fractal {
+    pub fn fbm(basis: Noise, octaves: u32, lacunarity: f32, gain: f32) -> Self {
+        Self {
+            basis,
+            kind: FractalKind::Fbm,
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+
+    pub fn ridged(basis: Noise, octaves: u32, lacunarity: f32, gain: f32) -> Self {
+        Self {
+            basis,
+            kind: FractalKind::Ridged,
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+
+    pub fn noise(&self, p: &Point) -> f32 {
+        let mut accum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut weight = 1.0;
+
+        (0..self.octaves).for_each(|_| {
+            let n = self.basis.sample(&(*p * frequency));
+
+            let octave = match self.kind {
+                FractalKind::Fbm => n,
+                FractalKind::Ridged => {
+                    let ridge = (1.0 - n.abs()).powi(2) * weight;
+                    weight = (ridge * 2.0).clamp(0.0, 1.0);
+                    ridge
+                }
+            };
+
+            accum += amplitude * octave;
+            total_amplitude += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        });
+
+        if total_amplitude > 0.0 {
+            accum / total_amplitude
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fractal;
+    use crate::{
+        noise::{perlin::Perlin, simplex::Simplex, Noise},
+        vector::Point,
+    };
+
+    fn points() -> impl Iterator<Item = Point> {
+        (0..300).map(|i| {
+            let i = i as f32;
+            Point::new(i * 0.37 + 0.1, i * 0.173 + 0.5, i * 0.091 + 2.25)
+        })
+    }
+
+    #[test]
+    fn a_single_octave_is_the_basis() {
+        let basis = Noise::Simplex(Simplex::new(1));
+        let fbm = Fractal::fbm(basis.clone(), 1, 2.0, 0.5);
+
+        for p in points() {
+            assert_eq!(fbm.noise(&p), basis.sample(&p));
+        }
+    }
+
+    #[test]
+    fn octaves_keep_the_range_of_the_basis() {
+        let basis = Noise::Perlin(Perlin::new(2));
+        let fbm = Fractal::fbm(basis.clone(), 6, 2.0, 0.5);
+        let ridged = Fractal::ridged(basis, 6, 2.0, 0.5);
+
+        for p in points() {
+            let value = fbm.noise(&p);
+            assert!((-1.0..=1.0).contains(&value), "fbm {value} at {p:?}");
+            let value = ridged.noise(&p);
+            assert!((0.0..=1.0).contains(&value), "ridged {value} at {p:?}");
+        }
+    }
+
+    #[test]
+    fn no_octaves_is_zero() {
+        let fractal = Fractal::fbm(Noise::Simplex(Simplex::default()), 0, 2.0, 0.5);
+        assert_eq!(fractal.noise(&Point::new(0.3, 0.2, 0.1)), 0.0);
+    }
+}
@@ -1 +1,56 @@
+pub mod fractal;
 pub mod perlin;
+pub mod simplex;
+pub mod worley;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::vector::Point;
+
+use self::{perlin::Perlin, simplex::Simplex, worley::Worley};
+
+/// A single-octave noise function, used as the basis of a `Fractal`.
+#[derive(Clone)]
+pub enum Noise {
+    Perlin(Perlin),
+    Simplex(Simplex),
+    Worley(Worley),
+}
+
+impl Noise {
+    pub fn sample(&self, p: &Point) -> f32 {
+        match self {
+            Noise::Perlin(perlin) => perlin.improved_noise(p),
+            Noise::Simplex(simplex) => simplex.noise(p),
+            Noise::Worley(worley) => worley.noise(p),
+        }
+    }
+}
+
+/// Returns the numbers `0..256` shuffled from `seed`.
+fn shuffled_permutation(seed: u64) -> [u8; 256] {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut permutation: [u8; 256] = std::array::from_fn(|i| i as u8);
+    permutation.shuffle(&mut rng);
+
+    permutation
+}
+
+/// Duplicates a permutation so lookups can index past 255 without wrapping.
+fn doubled_permutation(permutation: [u8; 256]) -> [u8; 512] {
+    std::array::from_fn(|i| permutation[i & 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shuffled_permutation;
+
+    #[test]
+    fn shuffled_permutations_hold_every_index_once() {
+        let mut permutation = shuffled_permutation(3);
+        assert_ne!(permutation, std::array::from_fn(|i| i as u8));
+
+        permutation.sort_unstable();
+        assert_eq!(permutation, std::array::from_fn(|i| i as u8));
+    }
+}
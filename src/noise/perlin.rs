@@ -1,9 +1,35 @@
+use std::sync::Arc;
+
 use crate::{grad, lerp, vector::Point};
 
-#[derive(Clone, Default)]
-pub struct Perlin;
+use super::{doubled_permutation, shuffled_permutation};
+
+/// Improved Perlin noise. The permutation table is built once and shared between
+/// clones; `Perlin::default()` uses Ken Perlin's reference permutation.
+#[derive(Clone)]
+pub struct Perlin {
+    permutation: Arc<[u8; 512]>,
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::from_permutation(Self::PERMUTATION)
+    }
+}
 
 impl Perlin {
+    /// Builds a noise generator whose permutation is shuffled from `seed`, so
+    /// generators with different seeds produce different noise.
+    pub fn new(seed: u64) -> Self {
+        Self::from_permutation(shuffled_permutation(seed))
+    }
+
+    fn from_permutation(permutation: [u8; 256]) -> Self {
+        Self {
+            permutation: Arc::new(doubled_permutation(permutation)),
+        }
+    }
+
     #[rustfmt::skip]
     pub fn improved_noise(&self, p: &Point) -> f32 {
         let x =p.x - p.x.floor(); // Find relative x, y, z
@@ -18,8 +44,7 @@ impl Perlin {
         let v = Self::fade(y); // for each of x, y, z.
         let w = Self::fade(z);
 
-        let mut p = vec![0; 512];
-        Self::permute(&mut p);
+        let p = &self.permutation;
 
         // Hash coordinates of the 8 cube.
         let a = p[xx] as usize + yy;
@@ -53,18 +78,11 @@ impl Perlin {
         accum.abs()
     }
 
-    fn permute(p: &mut [i32]) {
-        for i in 0..256 {
-            p[i] = Self::PERMUTATION[i];
-            p[256 + i] = p[i];
-        }
-    }
-
     fn fade(t: f32) -> f32 {
         t * t * t * (t * (t * 6. - 15.) + 10.)
     }
 
-    const PERMUTATION: [i32; 256] = [
+    const PERMUTATION: [u8; 256] = [
         151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30,
         69, 142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94,
         252, 219, 203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171,
@@ -80,3 +98,53 @@ impl Perlin {
         138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141, 128, 195, 78, 66, 215, 61, 156, 180,
     ];
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Perlin;
+    use crate::vector::Point;
+
+    fn points() -> impl Iterator<Item = Point> {
+        (0..200).map(|i| {
+            let i = i as f32;
+            Point::new(i * 0.37, i * 0.173 + 0.5, i * 0.091 + 2.25)
+        })
+    }
+
+    #[test]
+    fn seeded_noise_is_reproducible() {
+        let perlin = Perlin::new(42);
+        let again = Perlin::new(42);
+        let clone = perlin.clone();
+
+        for p in points() {
+            assert_eq!(perlin.improved_noise(&p), again.improved_noise(&p));
+            assert_eq!(perlin.improved_noise(&p), clone.improved_noise(&p));
+        }
+    }
+
+    #[test]
+    fn seeds_change_the_noise() {
+        let first = Perlin::new(1);
+        let second = Perlin::new(2);
+
+        let differing = points()
+            .filter(|p| first.improved_noise(p) != second.improved_noise(p))
+            .count();
+        assert!(differing > 150, "only {differing} points differ");
+    }
+
+    #[test]
+    fn noise_stays_in_range_and_vanishes_on_the_lattice() {
+        for perlin in [Perlin::default(), Perlin::new(7)] {
+            for p in points() {
+                let noise = perlin.improved_noise(&p);
+                assert!((-1.0..=1.0).contains(&noise), "noise {noise} at {p:?}");
+                assert!(perlin.turbulence(&p, 7) >= 0.0);
+            }
+
+            let lattice = Point::new(3.0, 5.0, 8.0);
+            assert_eq!(perlin.improved_noise(&lattice), 0.0);
+        }
+    }
+}
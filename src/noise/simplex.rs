@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use crate::vector::Point;
+
+use super::{doubled_permutation, shuffled_permutation};
+
+/// 3D simplex noise (Gustavson's formulation), in roughly `[-1, 1]`.
+#[derive(Clone)]
+pub struct Simplex {
+    permutation: Arc<[u8; 512]>,
+}
+
+impl Default for Simplex {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Simplex {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            permutation: Arc::new(doubled_permutation(shuffled_permutation(seed))),
+        }
+    }
+
+    pub fn noise(&self, p: &Point) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+
+        // Skew the input space to find the simplex cell containing the point.
+        let s = (p.x + p.y + p.z) * F3;
+        let i = (p.x + s).floor();
+        let j = (p.y + s).floor();
+        let k = (p.z + s).floor();
+
+        // Unskew the cell origin back to (x, y, z) space.
+        let t = (i + j + k) * G3;
+        let x0 = p.x - (i - t);
+        let y0 = p.y - (j - t);
+        let z0 = p.z - (k - t);
+
+        // Find which of the six tetrahedra the point is in.
+        let (i1, j1, k1, i2, j2, k2) = if x0 >= y0 {
+            if y0 >= z0 {
+                (1, 0, 0, 1, 1, 0)
+            } else if x0 >= z0 {
+                (1, 0, 0, 1, 0, 1)
+            } else {
+                (0, 0, 1, 1, 0, 1)
+            }
+        } else if y0 < z0 {
+            (0, 0, 1, 0, 1, 1)
+        } else if x0 < z0 {
+            (0, 1, 0, 0, 1, 1)
+        } else {
+            (0, 1, 0, 1, 1, 0)
+        };
+
+        let corners = [
+            (0, 0, 0, x0, y0, z0),
+            (
+                i1,
+                j1,
+                k1,
+                x0 - i1 as f32 + G3,
+                y0 - j1 as f32 + G3,
+                z0 - k1 as f32 + G3,
+            ),
+            (
+                i2,
+                j2,
+                k2,
+                x0 - i2 as f32 + 2.0 * G3,
+                y0 - j2 as f32 + 2.0 * G3,
+                z0 - k2 as f32 + 2.0 * G3,
+            ),
+            (
+                1,
+                1,
+                1,
+                x0 - 1.0 + 3.0 * G3,
+                y0 - 1.0 + 3.0 * G3,
+                z0 - 1.0 + 3.0 * G3,
+            ),
+        ];
+
+        let ii = (i as i32 & 255) as usize;
+        let jj = (j as i32 & 255) as usize;
+        let kk = (k as i32 & 255) as usize;
+        let perm = &self.permutation;
+
+        let total: f32 = corners
+            .iter()
+            .map(|&(di, dj, dk, x, y, z)| {
+                let t = 0.6 - x * x - y * y - z * z;
+                if t < 0.0 {
+                    return 0.0;
+                }
+
+                let hash = perm[ii + di + perm[jj + dj + perm[kk + dk] as usize] as usize];
+                let [gx, gy, gz] = GRADIENTS[hash as usize % 12];
+                let t2 = t * t;
+                t2 * t2 * (gx * x + gy * y + gz * z)
+            })
+            .sum();
+
+        // Scale the result to cover roughly [-1, 1].
+        32.0 * total
+    }
+}
+
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::Simplex;
+    use crate::vector::{Point, Vector3};
+
+    fn points() -> impl Iterator<Item = Point> {
+        (0..500).map(|i| {
+            let i = i as f32;
+            Point::new(i * 0.37 - 40.0, i * 0.173 + 0.5, -i * 0.091 + 2.25)
+        })
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let simplex = Simplex::new(11);
+        for p in points() {
+            let noise = simplex.noise(&p);
+            assert!((-1.0..=1.0).contains(&noise), "noise {noise} at {p:?}");
+        }
+    }
+
+    #[test]
+    fn noise_is_reproducible_per_seed() {
+        let (first, again, other) = (Simplex::new(3), Simplex::new(3), Simplex::new(4));
+
+        for p in points() {
+            assert_eq!(first.noise(&p), again.noise(&p));
+        }
+        let differing = points()
+            .filter(|p| first.noise(p) != other.noise(p))
+            .count();
+        assert!(differing > 400, "only {differing} points differ");
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let simplex = Simplex::default();
+        let step = Vector3::new(1e-3, -1e-3, 1e-3);
+
+        for p in points() {
+            let change = (simplex.noise(&p) - simplex.noise(&(p + step))).abs();
+            assert!(change < 0.05, "noise jumps by {change} at {p:?}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::vector::Point;
+
+/// Which distance a `Worley` noise returns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WorleyFeature {
+    /// Distance to the closest feature point.
+    #[default]
+    F1,
+    /// Distance to the second closest feature point.
+    F2,
+    /// `F2 - F1`, which outlines the cell borders.
+    F2MinusF1,
+}
+
+/// Worley (cellular) noise with one feature point per unit cell.
+#[derive(Clone, Default)]
+pub struct Worley {
+    pub seed: u32,
+    pub feature: WorleyFeature,
+}
+
+impl Worley {
+    pub fn new(seed: u32, feature: WorleyFeature) -> Self {
+        Self { seed, feature }
+    }
+
+    pub fn noise(&self, p: &Point) -> f32 {
+        let cell = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+
+        let mut f1 = f32::MAX;
+        let mut f2 = f32::MAX;
+
+        // The two closest points are always within the neighbouring cells.
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let (x, y, z) = (cell.0 + dx, cell.1 + dy, cell.2 + dz);
+                    let feature = self.feature_point(x, y, z);
+                    let distance = (feature - p).length_squared();
+
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+
+        match self.feature {
+            WorleyFeature::F1 => f1.sqrt(),
+            WorleyFeature::F2 => f2.sqrt(),
+            WorleyFeature::F2MinusF1 => f2.sqrt() - f1.sqrt(),
+        }
+    }
+
+    /// Position of the feature point of cell `(x, y, z)`.
+    fn feature_point(&self, x: i32, y: i32, z: i32) -> Point {
+        let cell_hash = mix(mix(mix(self.seed ^ x as u32) ^ y as u32) ^ z as u32);
+        let jitter = |axis: u32| mix(cell_hash ^ axis) as f32 / u32::MAX as f32;
+
+        Point::new(
+            x as f32 + jitter(0x68e3_1da4),
+            y as f32 + jitter(0xb529_7a4d),
+            z as f32 + jitter(0x1b56_c4e9),
+        )
+    }
+}
+
+/// Integer hash with good avalanche behaviour (from the "lowbias32" family).
+fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Worley, WorleyFeature};
+    use crate::vector::Point;
+
+    fn points() -> impl Iterator<Item = Point> {
+        (0..300).map(|i| {
+            let i = i as f32;
+            Point::new(i * 0.37 - 40.0, i * 0.173 + 0.5, -i * 0.091 + 2.25)
+        })
+    }
+
+    #[test]
+    fn distances_are_ordered_and_bounded() {
+        let f1 = Worley::new(9, WorleyFeature::F1);
+        let f2 = Worley::new(9, WorleyFeature::F2);
+        let border = Worley::new(9, WorleyFeature::F2MinusF1);
+
+        for p in points() {
+            let (near, far) = (f1.noise(&p), f2.noise(&p));
+            // The feature point of the cell of `p` is at most a cell diagonal away.
+            assert!((0.0..=3f32.sqrt()).contains(&near), "F1 {near} at {p:?}");
+            assert!(near <= far, "F1 {near} above F2 {far} at {p:?}");
+            assert!((border.noise(&p) - (far - near)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn feature_points_are_reproducible_per_seed() {
+        let feature = |seed| {
+            let p = Worley::new(seed, WorleyFeature::F1).feature_point(2, -3, 4);
+            [p.x, p.y, p.z]
+        };
+
+        assert_eq!(feature(5), feature(5));
+        assert_ne!(feature(5), feature(6));
+
+        // The noise vanishes on the feature points themselves.
+        let [x, y, z] = feature(5);
+        let worley = Worley::new(5, WorleyFeature::F1);
+        assert!(worley.noise(&Point::new(x, y, z)) < 1e-6);
+    }
+}
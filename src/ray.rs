@@ -5,6 +5,17 @@ pub struct Ray {
     pub origin: Point,
     pub direction: Vector3,
     pub time: f32,
+    pub differential: Option<RayDifferential>,
+}
+
+/// Auxiliary rays offset by one pixel in x and y, used to estimate the footprint
+/// of a ray on the surfaces it hits.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RayDifferential {
+    pub rx_origin: Point,
+    pub rx_direction: Vector3,
+    pub ry_origin: Point,
+    pub ry_direction: Vector3,
 }
 
 impl Ray {
@@ -13,10 +24,32 @@ impl Ray {
             origin,
             direction,
             time,
+            differential: None,
         }
     }
 
+    pub fn with_differential(mut self, differential: Option<RayDifferential>) -> Self {
+        self.differential = differential;
+        self
+    }
+
     pub fn at(&self, t: f32) -> Point {
         self.origin + t * self.direction
     }
 }
+
+impl RayDifferential {
+    /// Moves the offset rays to the offset hit points `p + dpdx` and `p + dpdy`,
+    /// and bends their directions with `scatter`, the same way the main ray was.
+    pub fn scattered<F>(&self, p: &Point, dpdx: &Vector3, dpdy: &Vector3, scatter: F) -> Self
+    where
+        F: Fn(&Vector3) -> Vector3,
+    {
+        Self {
+            rx_origin: *p + dpdx,
+            rx_direction: scatter(&self.rx_direction),
+            ry_origin: *p + dpdy,
+            ry_direction: scatter(&self.ry_direction),
+        }
+    }
+}
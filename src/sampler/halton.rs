@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use super::{hash, mix_bits, permutation_element, Pcg32, Sampler, ONE_MINUS_EPSILON};
+
+/// Number of dimensions drawn from the Halton sequence; later ones are random.
+const DIMENSIONS: usize = 256;
+
+/// Halton sequence, one prime base per dimension, with Owen scrambling seeded
+/// per pixel so neighbouring pixels do not share the same points.
+#[derive(Clone, Debug)]
+pub struct HaltonSampler {
+    primes: Arc<[u32]>,
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+    rng: Pcg32,
+}
+
+impl Default for HaltonSampler {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            primes: first_primes(DIMENSIONS).into(),
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    fn next(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        match self.primes.get(dimension as usize) {
+            Some(&base) => owen_scrambled_radical_inverse(
+                self.index,
+                base,
+                hash(self.pixel, dimension, self.seed),
+            ),
+            None => self.rng.next_f32(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash(pixel, index, self.seed), index as u64);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.next(), self.next())
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+
+    fn description(&self) -> String {
+        "halton".to_owned()
+    }
+}
+
+/// Radical inverse of `index` in `base`, with every digit permuted by a hash of
+/// the digits before it.
+fn owen_scrambled_radical_inverse(mut index: u32, base: u32, seed: u64) -> f32 {
+    let inv_base = 1.0 / base as f32;
+    let mut inv_base_m = 1.0f32;
+    let mut reversed_digits = 0u64;
+
+    // Keep going past the last non-zero digit, so the trailing zeros are
+    // scrambled too, until the digits fall below float precision.
+    while 1.0 - (base - 1) as f32 * inv_base_m < 1.0 {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit_seed = mix_bits(seed ^ reversed_digits) as u32;
+        let digit = permutation_element(digit, base, digit_seed);
+
+        reversed_digits = reversed_digits * base as u64 + digit as u64;
+        inv_base_m *= inv_base;
+        index = next;
+    }
+
+    (inv_base_m * reversed_digits as f32).min(ONE_MINUS_EPSILON)
+}
+
+fn first_primes(count: usize) -> Vec<u32> {
+    let mut primes: Vec<u32> = Vec::with_capacity(count);
+    let mut candidate = 2;
+
+    while primes.len() < count {
+        if primes
+            .iter()
+            .take_while(|&&p| p * p <= candidate)
+            .all(|&p| candidate % p != 0)
+        {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+
+    primes
+}
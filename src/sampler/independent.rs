@@ -0,0 +1,49 @@
+use super::{hash, Pcg32, Sampler};
+
+/// Uniform random values with no structure across samples.
+#[derive(Clone, Debug)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl Default for IndependentSampler {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.rng = Pcg32::new(hash(pixel, index, self.seed), index as u64);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.next_f32()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.next_f32(), self.rng.next_f32())
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+
+    fn description(&self) -> String {
+        "independent".to_owned()
+    }
+}
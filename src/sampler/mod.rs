@@ -0,0 +1,304 @@
+mod halton;
+mod independent;
+mod sobol;
+mod stratified;
+
+pub use self::halton::HaltonSampler;
+pub use self::independent::IndependentSampler;
+pub use self::sobol::SobolSampler;
+pub use self::stratified::StratifiedSampler;
+
+/// Source of the sample values used by the camera and the materials.
+///
+/// Each pixel sample is a sequence of dimensions: every call to `get_1d` consumes
+/// one and every call to `get_2d` consumes two. Samplers that distribute their
+/// values well across the samples of a pixel (stratified and low-discrepancy ones)
+/// make images converge faster than independent random numbers.
+pub trait Sampler: Send + Sync {
+    /// Prepares the sampler for sample `index` of `pixel`, restarting at the first
+    /// dimension.
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+    /// Returns the next dimension, in `[0, 1)`.
+    fn get_1d(&mut self) -> f32;
+    /// Returns the next two dimensions, each in `[0, 1)`.
+    fn get_2d(&mut self) -> (f32, f32);
+    /// Replaces the seed that decorrelates this sampler from other renders.
+    fn set_seed(&mut self, seed: u64);
+    /// Returns a fresh copy of the sampler, for another thread or pixel.
+    fn clone_box(&self) -> Box<dyn Sampler>;
+    /// Names the sampler and its settings, leaving out the seed, to tell apart
+    /// renders that would draw different samples.
+    fn description(&self) -> String;
+}
+
+/// Largest `f32` below one, so samples never reach the end of `[0, 1)`.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Finalizer from MurmurHash3, used to turn structured inputs into well-mixed bits.
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+/// Hashes a pixel sample dimension together with a seed.
+pub(crate) fn hash(pixel: (u32, u32), dimension: u32, seed: u64) -> u64 {
+    let packed = ((pixel.0 as u64) << 32) | pixel.1 as u64;
+    mix_bits(mix_bits(mix_bits(packed ^ seed) ^ dimension as u64) ^ seed.rotate_left(17))
+}
+
+/// Hashes a byte string, giving the same value on every platform and compiler
+/// version so the hash can be stored.
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.chunks(8).fold(bytes.len() as u64, |hash, chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        mix_bits(hash ^ u64::from_le_bytes(word))
+    })
+}
+
+/// Converts 32 random bits to a float in `[0, 1)`.
+fn to_unit_float(bits: u32) -> f32 {
+    (bits as f32 * (-32f32).exp2()).min(ONE_MINUS_EPSILON)
+}
+
+/// Returns element `i` of a random permutation of `0..n` chosen by `seed`,
+/// without building the permutation (Kensler, "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    if n <= 1 {
+        return 0;
+    }
+
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+/// Small PCG32 generator, cheap enough to seed for every pixel sample.
+#[derive(Clone, Debug)]
+pub(crate) struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    pub(crate) fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(0x5851_f42d_4c95_7f2d)
+            .wrapping_add(self.increment);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        to_unit_float(self.next_u32())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        hash_bytes, permutation_element, to_unit_float, HaltonSampler, IndependentSampler, Sampler,
+        SobolSampler, StratifiedSampler,
+    };
+
+    fn samplers(seed: u64) -> Vec<Box<dyn Sampler>> {
+        vec![
+            Box::new(IndependentSampler::new(seed)),
+            Box::new(StratifiedSampler::new(4, 4, true, seed)),
+            Box::new(StratifiedSampler::new(4, 4, false, seed)),
+            Box::new(HaltonSampler::new(seed)),
+            Box::new(SobolSampler::new(16, seed)),
+        ]
+    }
+
+    /// The first `dimensions` values of sample `index` of `pixel`, alternating
+    /// between 1D and 2D requests.
+    fn values(
+        sampler: &mut dyn Sampler,
+        pixel: (u32, u32),
+        index: u32,
+        dimensions: u32,
+    ) -> Vec<f32> {
+        sampler.start_pixel_sample(pixel, index);
+        let mut values = Vec::new();
+        while values.len() < dimensions as usize {
+            values.push(sampler.get_1d());
+            let (x, y) = sampler.get_2d();
+            values.extend([x, y]);
+        }
+        values
+    }
+
+    /// Checks that the values fall into `values.len()` equal intervals once each.
+    fn assert_stratified(values: impl IntoIterator<Item = f32>, description: &str) {
+        let values = values.into_iter().collect::<Vec<_>>();
+        let mut strata = values
+            .iter()
+            .map(|value| (value * values.len() as f32) as usize)
+            .collect::<Vec<_>>();
+        strata.sort_unstable();
+        assert_eq!(
+            strata,
+            (0..values.len()).collect::<Vec<_>>(),
+            "{description}: {values:?}"
+        );
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_interval() {
+        for mut sampler in samplers(1) {
+            for index in 0..64 {
+                // Past the 256 Halton dimensions too.
+                for value in values(sampler.as_mut(), (index, 7), index, 300) {
+                    assert!(
+                        (0.0..1.0).contains(&value),
+                        "{}: {value}",
+                        sampler.description()
+                    );
+                }
+            }
+        }
+        assert!(to_unit_float(u32::MAX) < 1.0);
+    }
+
+    #[test]
+    fn samples_are_reproducible_per_seed() {
+        for (mut first, mut again) in samplers(5).into_iter().zip(samplers(5)) {
+            let mut clone = first.clone_box();
+            for index in 0..16 {
+                let expected = values(first.as_mut(), (3, 4), index, 12);
+                assert_eq!(expected, values(again.as_mut(), (3, 4), index, 12));
+                assert_eq!(expected, values(clone.as_mut(), (3, 4), index, 12));
+            }
+        }
+
+        for (mut first, mut other) in samplers(5).into_iter().zip(samplers(6)) {
+            let differing = (0..16)
+                .filter(|&index| {
+                    values(first.as_mut(), (3, 4), index, 12)
+                        != values(other.as_mut(), (3, 4), index, 12)
+                })
+                .count();
+            assert!(
+                differing > 8,
+                "{}: seeds barely differ",
+                first.description()
+            );
+        }
+    }
+
+    /// The first three dimensions of the 16 samples of a pixel.
+    fn pixel_samples(mut sampler: Box<dyn Sampler>) -> Vec<Vec<f32>> {
+        (0..16)
+            .map(|index| values(sampler.as_mut(), (5, 9), index, 3))
+            .collect()
+    }
+
+    #[test]
+    fn stratified_samples_cover_every_stratum() {
+        let samplers: [Box<dyn Sampler>; 3] = [
+            Box::new(StratifiedSampler::new(4, 4, true, 2)),
+            Box::new(StratifiedSampler::new(4, 4, false, 2)),
+            Box::new(SobolSampler::new(16, 2)),
+        ];
+
+        for sampler in samplers {
+            let description = sampler.description();
+            let samples = pixel_samples(sampler);
+            assert_stratified(samples.iter().map(|values| values[0]), &description);
+
+            let mut cells = samples
+                .iter()
+                .map(|values| ((values[1] * 4.0) as usize, (values[2] * 4.0) as usize))
+                .collect::<Vec<_>>();
+            cells.sort_unstable();
+            cells.dedup();
+            assert_eq!(cells.len(), 16, "{description}: {samples:?}");
+        }
+    }
+
+    #[test]
+    fn sobol_points_are_stratified_along_each_axis() {
+        let samples = pixel_samples(Box::new(SobolSampler::new(16, 8)));
+
+        for dimension in 0..3 {
+            assert_stratified(samples.iter().map(|values| values[dimension]), "sobol");
+        }
+    }
+
+    #[test]
+    fn halton_dimensions_are_stratified_in_their_bases() {
+        let samples = pixel_samples(Box::new(HaltonSampler::new(8)));
+
+        assert_stratified(samples.iter().map(|values| values[0]), "base 2");
+        assert_stratified(samples[..9].iter().map(|values| values[1]), "base 3");
+        assert_stratified(samples[..5].iter().map(|values| values[2]), "base 5");
+    }
+
+    #[test]
+    fn permutation_elements_form_a_permutation() {
+        for n in [1, 2, 5, 16, 100] {
+            for seed in [0, 1, 0xdead_beef] {
+                let mut elements = (0..n)
+                    .map(|i| permutation_element(i, n, seed))
+                    .collect::<Vec<_>>();
+                elements.sort_unstable();
+                assert_eq!(elements, (0..n).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn byte_hashes_are_stable() {
+        assert_eq!(hash_bytes(b""), 0);
+        assert_eq!(hash_bytes(b"raytracer"), hash_bytes(b"raytracer"));
+        assert_ne!(hash_bytes(b"raytracer"), hash_bytes(b"raytracer\0"));
+        assert_eq!(hash_bytes(b"raytracer"), 14027579697977809364);
+    }
+}
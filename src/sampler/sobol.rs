@@ -0,0 +1,110 @@
+use super::{hash, permutation_element, to_unit_float, Sampler};
+
+/// Owen-scrambled Sobol points, padded from 2D: every pair of dimensions uses the
+/// first two Sobol dimensions with its own scrambling and its own shuffle of the
+/// sample order, so there is no limit on the number of dimensions. Works best
+/// when `samples_per_pixel` is a power of two.
+#[derive(Clone, Debug)]
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel,
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Shuffles the sample order for the current dimension and returns the hash
+    /// used to scramble it.
+    fn shuffled_index(&mut self) -> (u32, u64) {
+        let seed = hash(self.pixel, self.dimension, self.seed);
+        self.dimension += 1;
+
+        let index = if self.index < self.samples_per_pixel {
+            permutation_element(self.index, self.samples_per_pixel, seed as u32)
+        } else {
+            self.index
+        };
+
+        (index, seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (index, seed) = self.shuffled_index();
+
+        to_unit_float(owen_scramble(van_der_corput(index), seed as u32))
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (index, seed) = self.shuffled_index();
+        self.dimension += 1;
+
+        (
+            to_unit_float(owen_scramble(van_der_corput(index), seed as u32)),
+            to_unit_float(owen_scramble(sobol_second(index), (seed >> 32) as u32)),
+        )
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+
+    fn description(&self) -> String {
+        format!("sobol, {} samples per pixel", self.samples_per_pixel)
+    }
+}
+
+/// First Sobol dimension: the bits of `index` mirrored around the binary point.
+fn van_der_corput(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// Second Sobol dimension, whose generator matrix is Pascal's triangle mod 2.
+fn sobol_second(mut index: u32) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+
+    result
+}
+
+/// Nested uniform scrambling of a base-2 sample, using the hash-based
+/// construction of Laine and Karras with improved constants.
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20_adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6c56);
+    v ^= v.wrapping_mul(0x53a2_2864);
+    v.reverse_bits()
+}
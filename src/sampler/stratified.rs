@@ -0,0 +1,94 @@
+use super::{hash, permutation_element, Pcg32, Sampler};
+
+/// Splits every dimension into `x_samples * y_samples` strata (a grid for 2D
+/// dimensions) and places each sample of a pixel in a different one. The strata
+/// are visited in a different random order for every dimension. Use it with a
+/// `samples_per_pixel` of `x_samples * y_samples`.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+    x_samples: u32,
+    y_samples: u32,
+    jitter: bool,
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    /// Without `jitter`, samples sit at the center of their stratum.
+    pub fn new(x_samples: u32, y_samples: u32, jitter: bool, seed: u64) -> Self {
+        Self {
+            x_samples: x_samples.max(1),
+            y_samples: y_samples.max(1),
+            jitter,
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+
+    fn stratum(&mut self) -> u32 {
+        let count = self.x_samples * self.y_samples;
+        let permutation_seed = hash(self.pixel, self.dimension, self.seed) as u32;
+        self.dimension += 1;
+
+        permutation_element(self.index % count, count, permutation_seed)
+    }
+
+    fn offset(&mut self) -> f32 {
+        if self.jitter {
+            self.rng.next_f32()
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::new(hash(pixel, index, self.seed), index as u64);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let count = (self.x_samples * self.y_samples) as f32;
+        let stratum = self.stratum();
+
+        (stratum as f32 + self.offset()) / count
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let stratum = self.stratum();
+        self.dimension += 1;
+        let x = stratum % self.x_samples;
+        let y = stratum / self.x_samples;
+
+        (
+            (x as f32 + self.offset()) / self.x_samples as f32,
+            (y as f32 + self.offset()) / self.y_samples as f32,
+        )
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "stratified {}x{}{}",
+            self.x_samples,
+            self.y_samples,
+            if self.jitter { ", jittered" } else { "" }
+        )
+    }
+}
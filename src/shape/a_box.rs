@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use crate::{
     hittable::HittableList,
     material::Surface,
@@ -6,8 +8,10 @@ use crate::{
 
 use super::{Element, Quad};
 
-pub fn a_box(a: &Point, b: &Point, material: Surface) -> HittableList {
+/// The six sides share `material`, so they get the same material id.
+pub fn a_box(a: &Point, b: &Point, material: impl Into<Arc<Surface>>) -> HittableList {
     let mut sides = HittableList::default();
+    let material = material.into();
 
     let min = Point::new(f32::min(a.x, b.x), f32::min(a.y, b.y), f32::min(a.z, b.z));
     let max = Point::new(f32::max(a.x, b.x), f32::max(a.y, b.y), f32::max(a.z, b.z));
@@ -5,7 +5,9 @@ pub mod sphere;
 use crate::{
     hittable::{HitRecord, Hittable, HittableList},
     interval::Interval,
+    material::{Material, Surface},
     ray::Ray,
+    texture::TexCoord,
     vector::Vector3,
 };
 
@@ -38,27 +40,44 @@ impl Element {
             object: Box::new(object),
         }
     }
-}
 
-impl Hittable for Element {
-    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    /// The surfaces of every primitive in the element, in the order they are
+    /// tested for hits.
+    pub fn surfaces(&self) -> Vec<&Surface> {
+        match self {
+            Element::Sphere(sphere) => vec![&*sphere.material],
+            Element::Quad(quad) => vec![&*quad.material],
+            Element::Box(sides) => sides.objects.iter().flat_map(Element::surfaces).collect(),
+            Element::Translate { object, .. } | Element::RotateY { object, .. } => {
+                object.surfaces()
+            }
+        }
+    }
+
+    /// The closest hit with the geometry of the element in `ray_t`, in world
+    /// space, whether or not the surface cuts it out.
+    fn intersect(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         match self {
             Element::Sphere(ref s) => s.hit(ray, ray_t),
             Element::Quad(ref q) => q.hit(ray, ray_t),
-            Element::Box(ref b) => b.hit(ray, ray_t),
+            Element::Box(ref b) => {
+                let mut rec = None;
+                let mut closest_so_far = ray_t.max;
+                for side in &b.objects {
+                    if let Some(hit) =
+                        side.intersect(ray, &Interval::new(ray_t.min, closest_so_far))
+                    {
+                        closest_so_far = hit.t;
+                        rec = Some(hit);
+                    }
+                }
+                rec
+            }
             Element::Translate { offset, object } => {
                 // Move the ray backwards by the offset
-                let offset_ray = Ray {
-                    origin: ray.origin - offset,
-                    direction: ray.direction,
-                    time: ray.time,
-                };
+                let offset_ray = Ray::new(ray.origin - offset, ray.direction, ray.time);
                 // Determine where (if any) an intersection occurs along the offset ray
-                let Some(mut hit_record) =
-                    object.hit(&offset_ray, ray_t)
-                else {
-                    return None;
-                };
+                let mut hit_record = object.intersect(&offset_ray, ray_t)?;
                 // Move the intersection point forwards by the offset
                 hit_record.p += *offset;
                 Some(hit_record)
@@ -69,8 +88,8 @@ impl Hittable for Element {
                 object,
             } => {
                 // Change the ray from world space to object space
-                let mut origin = ray.origin.clone();
-                let mut direction = ray.direction.clone();
+                let mut origin = ray.origin;
+                let mut direction = ray.direction;
 
                 origin.x = cos_theta * ray.origin.x - sin_theta * ray.origin.z;
                 origin.z = sin_theta * ray.origin.x + cos_theta * ray.origin.z;
@@ -78,18 +97,10 @@ impl Hittable for Element {
                 direction.x = cos_theta * ray.direction.x - sin_theta * ray.direction.z;
                 direction.z = sin_theta * ray.direction.x + cos_theta * ray.direction.z;
 
-                let rotated_r = Ray {
-                    origin,
-                    direction,
-                    time: ray.time,
-                };
+                let rotated_r = Ray::new(origin, direction, ray.time);
 
                 // Determine where (if any) an intersection occurs in object space
-                let Some(mut hit_record) =
-                    object.hit(&rotated_r, ray_t)
-                else {
-                    return None;
-                };
+                let mut hit_record = object.intersect(&rotated_r, ray_t)?;
 
                 // Change the intersection point from object space to world space
                 let mut p = hit_record.p;
@@ -103,9 +114,87 @@ impl Hittable for Element {
 
                 hit_record.p = p;
                 hit_record.normal = normal;
+                hit_record.dpdu = Self::rotate_to_world(*sin_theta, *cos_theta, &hit_record.dpdu);
+                hit_record.dpdv = Self::rotate_to_world(*sin_theta, *cos_theta, &hit_record.dpdv);
 
                 Some(hit_record)
             }
         }
     }
+
+    fn rotate_to_world(sin_theta: f32, cos_theta: f32, v: &Vector3) -> Vector3 {
+        Vector3::new(
+            cos_theta * v.x + sin_theta * v.z,
+            v.y,
+            -sin_theta * v.x + cos_theta * v.z,
+        )
+    }
+}
+
+impl Hittable for Element {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
+        // Cutouts are tested once the hit is in world space, so masks that depend
+        // on the point cut the same region however the primitive is moved. A hit
+        // that is cut out is skipped by searching again from just past it.
+        let mut min = ray_t.min;
+        loop {
+            let mut rec = self.intersect(ray, &Interval::new(min, ray_t.max))?;
+            if rec.material.alpha_test(&TexCoord::from(&rec)) {
+                rec.element = Some(self);
+                return Some(rec);
+            }
+            min = rec.t.next_up();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Element, Quad};
+    use crate::{
+        hittable::Hittable,
+        interval::Interval,
+        material::Surface,
+        ray::Ray,
+        texture::Texture,
+        vector::{Color, Point, Vector3},
+    };
+
+    /// A unit quad at the origin, cut out wherever a world-space checker of unit
+    /// cells is black, moved along x by `offset`.
+    fn translated_cutout(offset: f32) -> Element {
+        let surface = Surface::Cutout {
+            surface: Box::default(),
+            opacity: Texture::Checkered {
+                even: Color::white(),
+                odd: Color::black(),
+                scale: 1.0,
+            },
+        };
+        let quad = Quad::new(
+            Point::new(0.0, 0.0, 0.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            surface,
+        );
+
+        Element::Translate {
+            offset: Vector3::new(offset, 0.0, 0.0),
+            object: Box::new(Element::Quad(quad)),
+        }
+    }
+
+    fn hits_at(element: &Element, x: f32) -> bool {
+        let ray = Ray::new(Point::new(x, 0.5, 5.0), Vector3::new(0.0, 0.0, -1.0), 0.0);
+        element
+            .hit(&ray, &Interval::new(0.001, f32::INFINITY))
+            .is_some()
+    }
+
+    #[test]
+    fn cutouts_are_tested_in_world_space() {
+        // The quad is opaque in its own frame, but lands on a black cell at x = 1.
+        assert!(!hits_at(&translated_cutout(1.0), 1.5));
+        assert!(hits_at(&translated_cutout(2.0), 2.5));
+    }
 }
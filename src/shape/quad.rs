@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use crate::{
     dot,
     hittable::{HitRecord, Hittable},
@@ -12,13 +14,15 @@ pub struct Quad {
     pub u: Vector3,
     pub v: Vector3,
     pub w: Vector3,
-    pub material: Surface,
+    pub material: Arc<Surface>,
     pub normal: Vector3,
     pub d: f32,
 }
 
 impl Quad {
-    pub fn new(q: Point, u: Vector3, v: Vector3, material: Surface) -> Self {
+    /// Pass a shared `Arc` as `material` to give several primitives the same
+    /// material id.
+    pub fn new(q: Point, u: Vector3, v: Vector3, material: impl Into<Arc<Surface>>) -> Self {
         let n = u.cross(&v);
         let normal = n.unit();
         let d = normal.dot(&q);
@@ -29,7 +33,7 @@ impl Quad {
             u,
             v,
             w,
-            material,
+            material: material.into(),
             normal,
             d,
         }
@@ -48,7 +52,7 @@ impl Quad {
 }
 
 impl Hittable for Quad {
-    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let denom = self.normal.dot(&ray.direction);
 
         // No hit if the ray is parallel to the plane.
@@ -84,6 +88,7 @@ impl Hittable for Quad {
             v,
         );
         rec.set_face_normal(ray, &self.normal);
+        rec.set_tangents(self.u, self.v);
 
         Some(rec)
     }
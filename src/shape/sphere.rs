@@ -1,4 +1,4 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, sync::Arc};
 
 use crate::{
     hittable::{HitRecord, Hittable},
@@ -12,29 +12,36 @@ pub struct Sphere {
     pub center: Point,
     pub radius: f32,
     pub radius_squared: f32,
-    pub material: Surface,
+    pub material: Arc<Surface>,
     pub is_moving: bool,
     pub center_vec: Point,
 }
 
 impl Sphere {
-    pub fn new(center: Point, radius: f32, material: Surface) -> Self {
+    /// Pass a shared `Arc` as `material` to give several primitives the same
+    /// material id.
+    pub fn new(center: Point, radius: f32, material: impl Into<Arc<Surface>>) -> Self {
         Self {
             center,
             radius,
             radius_squared: radius * radius,
-            material,
+            material: material.into(),
             is_moving: false,
             center_vec: center,
         }
     }
 
-    pub fn new_moving(center1: Point, center2: Point, radius: f32, material: Surface) -> Self {
+    pub fn new_moving(
+        center1: Point,
+        center2: Point,
+        radius: f32,
+        material: impl Into<Arc<Surface>>,
+    ) -> Self {
         Self {
             center: center1,
             radius,
             radius_squared: radius * radius,
-            material,
+            material: material.into(),
             is_moving: true,
             center_vec: center2 - center1,
         }
@@ -59,10 +66,21 @@ impl Sphere {
         let v = theta / PI;
         (u, v)
     }
+
+    fn get_sphere_tangents(&self, p: Vector3) -> (Vector3, Vector3) {
+        // p: a given point on the sphere of radius one, centered at the origin.
+        // Derivatives of the parametrization used by `get_sphere_uv`, scaled by the radius.
+        let sin_theta = (1.0 - p.y * p.y).sqrt().max(1e-4);
+
+        let dpdu = Vector3::new(p.z, 0.0, -p.x) * (2. * PI * self.radius);
+        let dpdv = Vector3::new(-p.y * p.x / sin_theta, sin_theta, -p.y * p.z / sin_theta)
+            * (PI * self.radius);
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_t: &Interval) -> Option<HitRecord<'_>> {
         let center = if self.is_moving {
             self.center(ray.time)
         } else {
@@ -79,22 +97,26 @@ impl Hittable for Sphere {
         }
         let sqrtd = discriminant.sqrt();
 
-        let mut root = (-half_b - sqrtd) / a;
-        if !ray_t.contains(root) {
-            root = (-half_b + sqrtd) / a;
+        // Try the nearest root first; fall back to the far one when it lies outside
+        // the interval.
+        let roots = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a];
+        roots.into_iter().find_map(|root| {
             if !ray_t.contains(root) {
                 return None;
             }
-        }
 
-        let point = ray.at(root);
-        let outward_normal = (point - center) / self.radius;
+            let point = ray.at(root);
+            let outward_normal = (point - center) / self.radius;
+            let (u, v) = self.get_sphere_uv(outward_normal);
 
-        let (u, v) = self.get_sphere_uv(outward_normal);
+            let mut rec = HitRecord::new(point, outward_normal, root, &self.material, false, u, v);
+            rec.set_face_normal(ray, &outward_normal);
+            rec.local_p = point - center + self.center;
 
-        let mut rec = HitRecord::new(point, outward_normal, root, &self.material, false, u, v);
-        rec.set_face_normal(ray, &outward_normal);
+            let (dpdu, dpdv) = self.get_sphere_tangents(outward_normal);
+            rec.set_tangents(dpdu, dpdv);
 
-        Some(rec)
+            Some(rec)
+        })
     }
 }
@@ -0,0 +1,91 @@
+use crate::{
+    camera::Camera,
+    checkpoint::write_film,
+    film::Film,
+    hittable::HittableList,
+    material::Surface,
+    shape::{a_box::a_box, sphere::Sphere, Element},
+    texture::Texture,
+    vector::{Color, Point, Vector3},
+};
+
+/// A small scene with a textured ground, a diffuse, a glass and a rough metal
+/// sphere and a box, for tests that render.
+pub(crate) fn world() -> HittableList {
+    let mut world = HittableList::default();
+
+    let ground = Surface::Diffuse {
+        albedo: Texture::Checkered {
+            even: Color::new(0.2, 0.3, 0.1),
+            odd: Color::from_one(0.9),
+            scale: 0.5,
+        },
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(0., -1000., 0.),
+        1000.,
+        ground,
+    )));
+
+    let diffuse = Surface::Diffuse {
+        albedo: Texture::SolidColor(Color::new(0.4, 0.2, 0.1)),
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(-4., 1., 0.),
+        1.0,
+        diffuse,
+    )));
+
+    let glass = Surface::Refractive {
+        idx_of_refraction: 1.5,
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(0., 1., 0.),
+        1.0,
+        glass,
+    )));
+
+    let metal = Surface::Reflective {
+        albedo: Color::new(0.7, 0.6, 0.5),
+        fuzz: 0.3,
+    };
+    world.add(Element::Sphere(Sphere::new(
+        Point::new(4., 1., 0.),
+        1.0,
+        metal,
+    )));
+
+    let white = Surface::Diffuse {
+        albedo: Texture::SolidColor(Color::from_one(0.73)),
+    };
+    let a_box = Element::Box(a_box(&Point::zero(), &Point::new(1.2, 1.2, 1.2), white));
+    world.add(Element::Translate {
+        offset: Vector3::new(-1.5, 0., 2.5),
+        object: Box::new(Element::new_rotate_y(30., a_box)),
+    });
+
+    world
+}
+
+pub(crate) fn camera(image_width: u32, samples_per_pixel: u32) -> Camera {
+    Camera::new(
+        16.0 / 9.0,
+        image_width,
+        samples_per_pixel,
+        8,
+        20.0,
+        Point::new(13.0, 2.0, 3.0),
+        Point::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        0.0,
+        10.0,
+        Color::new(0.7, 0.8, 1.0),
+    )
+}
+
+/// The film as it is written to checkpoints, for comparing films bit for bit.
+pub(crate) fn film_bytes(film: &Film) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_film(&mut bytes, film).expect("writing to a Vec cannot fail");
+    bytes
+}
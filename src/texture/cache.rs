@@ -0,0 +1,113 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::error::Result;
+
+use super::{ColorSpace, ImageTexture};
+
+/// Decodes each image file once and hands out textures that share its pixels.
+#[derive(Default)]
+pub struct TextureCache {
+    images: HashMap<(PathBuf, Option<ColorSpace>), ImageTexture>,
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a texture for the image at `path`, decoding it only the first time
+    /// the path is seen. The returned texture uses the default filtering options
+    /// and can be customized without affecting other users of the same image.
+    pub fn load<P>(&mut self, path: P) -> Result<ImageTexture>
+    where
+        P: AsRef<Path>,
+    {
+        self.load_as(path, None)
+    }
+
+    /// Like `load`, decoding the image in `color_space` (or the one picked from its
+    /// pixel type, for `None`). The same file loaded in different color spaces is
+    /// cached once per color space.
+    pub fn load_as<P>(&mut self, path: P, color_space: Option<ColorSpace>) -> Result<ImageTexture>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let key = (
+            path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+            color_space,
+        );
+
+        if let Some(texture) = self.images.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let texture = ImageTexture::open_as(path, color_space)?;
+        self.images.insert(key, texture.clone());
+
+        Ok(texture)
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use image::{Rgb, RgbImage};
+
+    use super::TextureCache;
+    use crate::texture::ColorSpace;
+
+    fn temp_png(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("raytracer-{name}-{}.png", process::id()));
+        RgbImage::from_pixel(2, 2, Rgb([200, 100, 50]))
+            .save(&path)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn images_are_decoded_once_per_path_and_color_space() {
+        let path = temp_png("cache");
+        let mut cache = TextureCache::new();
+
+        let first = cache.load(&path).unwrap();
+        cache.load_as(&path, Some(ColorSpace::Linear)).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(first.color_space(), ColorSpace::Srgb);
+
+        // Once cached, the file is not read again.
+        fs::remove_file(&path).unwrap();
+        let again = cache.load(&path).unwrap();
+        assert_eq!(again.color_space(), ColorSpace::Srgb);
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(cache.load(&path).is_err());
+    }
+
+    #[test]
+    fn failed_loads_are_not_cached() {
+        let path = env::temp_dir().join(format!("raytracer-missing-{}.png", process::id()));
+        let mut cache = TextureCache::new();
+
+        assert!(cache.load(&path).is_err());
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,600 @@
+use std::{path::Path, sync::Arc};
+
+use image::{DynamicImage, ImageError};
+
+use super::TexCoord;
+use crate::{
+    error::{Error, Result},
+    lerp,
+    vector::Color,
+};
+
+/// How texels are reconstructed between pixel centers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Bilinear,
+    /// Catmull-Rom interpolation over the 4x4 neighbourhood.
+    Bicubic,
+}
+
+/// How texture coordinates outside `[0, 1]` are mapped back into the image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Wrap {
+    #[default]
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+/// Affine transform applied to `(u, v)` before the lookup: scale first, then a
+/// rotation in degrees around the origin, then the offset.
+#[derive(Clone, Copy, Debug)]
+pub struct UvTransform {
+    pub offset: (f32, f32),
+    pub scale: (f32, f32),
+    pub rotation: f32,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn apply(&self, u: f32, v: f32) -> (f32, f32) {
+        let (u, v) = (u * self.scale.0, v * self.scale.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        (
+            cos * u - sin * v + self.offset.0,
+            sin * u + cos * v + self.offset.1,
+        )
+    }
+
+    /// Applies the transform to a difference of coordinates, ignoring the offset.
+    pub fn apply_vector(&self, du: f32, dv: f32) -> (f32, f32) {
+        let (du, dv) = (du * self.scale.0, dv * self.scale.1);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+
+        (cos * du - sin * dv, sin * du + cos * dv)
+    }
+}
+
+/// How the color channels of an image are encoded. Alpha is always linear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// sRGB-encoded color, decoded to linear on load. Use it for albedo and
+    /// emission maps stored in 8 or 16 bits.
+    Srgb,
+    /// Data that is already linear, such as normal, roughness or HDR maps.
+    Linear,
+}
+
+impl ColorSpace {
+    /// Floating-point images are assumed linear and integer ones sRGB.
+    pub fn default_for(image: &DynamicImage) -> Self {
+        match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => ColorSpace::Linear,
+            _ => ColorSpace::Srgb,
+        }
+    }
+
+    fn to_linear(self, c: f32) -> f32 {
+        match self {
+            ColorSpace::Linear => c,
+            ColorSpace::Srgb if c <= 0.04045 => c / 12.92,
+            ColorSpace::Srgb => ((c + 0.055) / 1.055).powf(2.4),
+        }
+    }
+}
+
+/// One level of the MIP pyramid, stored as linear RGBA texels.
+#[derive(Clone)]
+struct Level {
+    width: u32,
+    height: u32,
+    texels: Vec<[f32; 4]>,
+}
+
+/// Image texture with a pre-built MIP pyramid. Lookups with a footprint blend the
+/// two nearest levels (trilinear filtering when combined with `Filter::Bilinear`).
+/// Clones share the pyramid. 8 and 16-bit images are normalized to `[0, 1]`;
+/// floating-point images keep their full range.
+#[derive(Clone)]
+pub struct ImageTexture {
+    levels: Arc<[Level]>,
+    color_space: ColorSpace,
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub transform: UvTransform,
+}
+
+impl ImageTexture {
+    /// Creates a texture from `image`, assuming the color space from its pixel type.
+    pub fn new(image: DynamicImage) -> Self {
+        let color_space = ColorSpace::default_for(&image);
+        Self::decode(image, color_space)
+    }
+
+    /// Creates a texture from `image`, whose color channels are in `color_space`.
+    pub fn decode(image: DynamicImage, color_space: ColorSpace) -> Self {
+        Self {
+            levels: build_pyramid(image, color_space).into(),
+            color_space,
+            filter: Filter::default(),
+            wrap: Wrap::default(),
+            transform: UvTransform::default(),
+        }
+    }
+
+    /// Reads and decodes the image file at `path`, assuming the color space from
+    /// its pixel type.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_as(path, None)
+    }
+
+    /// Reads and decodes the image file at `path`. A `color_space` of `None`
+    /// picks it from the pixel type.
+    pub fn open_as<P>(path: P, color_space: Option<ColorSpace>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        use image::io::Reader as ImageReader;
+
+        let path = path.as_ref();
+        let io_error = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let reader = ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(io_error)?;
+        if reader.format().is_none() {
+            return Err(Error::UnsupportedFormat {
+                path: path.to_path_buf(),
+            });
+        }
+
+        let image = reader.decode().map_err(|source| match source {
+            ImageError::Unsupported(_) => Error::UnsupportedFormat {
+                path: path.to_path_buf(),
+            },
+            ImageError::IoError(source) => io_error(source),
+            source => Error::Decode {
+                path: path.to_path_buf(),
+                source,
+            },
+        })?;
+
+        let color_space = color_space.unwrap_or_else(|| ColorSpace::default_for(&image));
+        Ok(Self::decode(image, color_space))
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: UvTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn color(&self, coord: &TexCoord) -> Color {
+        // If we have no texture data, then return solid cyan as a debugging aid.
+        if self.is_empty() {
+            return Color::new(0., 1., 1.);
+        }
+
+        let [r, g, b, _] = self.lookup(coord);
+        Color::new(r, g, b)
+    }
+
+    pub fn alpha(&self, u: f32, v: f32) -> f32 {
+        if self.is_empty() {
+            return 1.0;
+        }
+
+        self.sample(&self.levels[0], u, v)[3]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.levels[0].texels.is_empty()
+    }
+
+    /// Picks the MIP level from the footprint of the lookup and blends the two
+    /// levels around it.
+    fn lookup(&self, coord: &TexCoord) -> [f32; 4] {
+        let base = &self.levels[0];
+        let uv_scale = self
+            .transform
+            .scale
+            .0
+            .abs()
+            .max(self.transform.scale.1.abs());
+        let width = uv_scale
+            * f32::max(
+                (coord.dudx.abs() * base.width as f32).max(coord.dudy.abs() * base.width as f32),
+                (coord.dvdx.abs() * base.height as f32).max(coord.dvdy.abs() * base.height as f32),
+            );
+
+        let level = width
+            .max(1e-8)
+            .log2()
+            .clamp(0.0, (self.levels.len() - 1) as f32);
+        let fine = level.floor() as usize;
+        let t = level - fine as f32;
+
+        let fine_value = self.sample(&self.levels[fine], coord.u, coord.v);
+        if t == 0.0 {
+            return fine_value;
+        }
+        let coarse_value = self.sample(&self.levels[fine + 1], coord.u, coord.v);
+
+        [0, 1, 2, 3].map(|c| lerp(t, fine_value[c], coarse_value[c]))
+    }
+
+    /// Returns the filtered RGBA value of `level` at `(u, v)`.
+    fn sample(&self, level: &Level, u: f32, v: f32) -> [f32; 4] {
+        let (u, v) = self.transform.apply(u, v);
+        let v = 1.0 - v; // Flip V to image coordinates
+
+        // Continuous texel coordinates, with texel centers at half-integers.
+        let x = u * level.width as f32;
+        let y = v * level.height as f32;
+
+        match self.filter {
+            Filter::Nearest => self.texel(level, x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (i, j) = (x0 as i64, y0 as i64);
+
+                let weights = [
+                    (0, 0, (1.0 - fx) * (1.0 - fy)),
+                    (1, 0, fx * (1.0 - fy)),
+                    (0, 1, (1.0 - fx) * fy),
+                    (1, 1, fx * fy),
+                ];
+                self.weighted(
+                    level,
+                    weights.into_iter().map(|(di, dj, w)| (i + di, j + dj, w)),
+                )
+            }
+            Filter::Bicubic => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let wx = catmull_rom(x - x0);
+                let wy = catmull_rom(y - y0);
+                let (i, j) = (x0 as i64, y0 as i64);
+
+                let texels = (0..4).flat_map(|dj| {
+                    (0..4)
+                        .map(move |di| (i + di - 1, j + dj - 1, wx[di as usize] * wy[dj as usize]))
+                });
+                let mut value = self.weighted(level, texels);
+                // Catmull-Rom can overshoot around sharp edges.
+                value.iter_mut().for_each(|c| *c = c.max(0.0));
+                value[3] = value[3].min(1.0);
+                value
+            }
+        }
+    }
+
+    fn weighted(&self, level: &Level, texels: impl Iterator<Item = (i64, i64, f32)>) -> [f32; 4] {
+        texels.fold([0.0; 4], |mut acc, (i, j, w)| {
+            let texel = self.texel(level, i, j);
+            acc.iter_mut().zip(texel).for_each(|(a, t)| *a += w * t);
+            acc
+        })
+    }
+
+    fn texel(&self, level: &Level, i: i64, j: i64) -> [f32; 4] {
+        let i = self.wrap.apply(i, level.width);
+        let j = self.wrap.apply(j, level.height);
+
+        level.texels[(j * level.width + i) as usize]
+    }
+}
+
+/// Converts `image` to linear RGBA for the base level and box-filters it down to
+/// a single texel. Filtering happens after decoding so sRGB edges stay correct.
+fn build_pyramid(image: DynamicImage, color_space: ColorSpace) -> Vec<Level> {
+    let rgba = image.into_rgba32f();
+    let base = Level {
+        width: rgba.width(),
+        height: rgba.height(),
+        texels: rgba
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                [
+                    color_space.to_linear(r),
+                    color_space.to_linear(g),
+                    color_space.to_linear(b),
+                    a,
+                ]
+            })
+            .collect(),
+    };
+
+    if base.texels.is_empty() {
+        return vec![base];
+    }
+
+    let mut levels = vec![base];
+    loop {
+        let previous = levels.last().unwrap();
+        if previous.width <= 1 && previous.height <= 1 {
+            break;
+        }
+
+        let width = (previous.width / 2).max(1);
+        let height = (previous.height / 2).max(1);
+        let texel = |i: u32, j: u32| {
+            let i = i.min(previous.width - 1);
+            let j = j.min(previous.height - 1);
+            previous.texels[(j * previous.width + i) as usize]
+        };
+
+        let texels = (0..height)
+            .flat_map(|j| (0..width).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let quad = [
+                    texel(2 * i, 2 * j),
+                    texel(2 * i + 1, 2 * j),
+                    texel(2 * i, 2 * j + 1),
+                    texel(2 * i + 1, 2 * j + 1),
+                ];
+                [0, 1, 2, 3].map(|c| quad.iter().map(|t| t[c]).sum::<f32>() * 0.25)
+            })
+            .collect();
+
+        levels.push(Level {
+            width,
+            height,
+            texels,
+        });
+    }
+
+    levels
+}
+
+impl Wrap {
+    /// Maps a texel index into `[0, size)`.
+    fn apply(&self, index: i64, size: u32) -> u32 {
+        let size = size as i64;
+        let index = match self {
+            Wrap::Clamp => index.clamp(0, size - 1),
+            Wrap::Repeat => index.rem_euclid(size),
+            Wrap::Mirror => {
+                let period = index.rem_euclid(2 * size);
+                if period < size {
+                    period
+                } else {
+                    2 * size - 1 - period
+                }
+            }
+        };
+
+        index as u32
+    }
+}
+
+/// Catmull-Rom weights for the four texels around a sample at fraction `t`.
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use image::{DynamicImage, ImageBuffer, Rgb, Rgb32FImage, Rgba, RgbaImage};
+
+    use super::{build_pyramid, ColorSpace, Filter, ImageTexture, UvTransform, Wrap};
+    use crate::{error::Error, texture::TexCoord, vector::Point};
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("raytracer-{name}-{}", process::id()))
+    }
+
+    /// A linear image, one texel high, with the given gray values.
+    fn row(values: &[f32]) -> ImageTexture {
+        let data = values.iter().flat_map(|&value| [value; 3]).collect();
+        let image = Rgb32FImage::from_raw(values.len() as u32, 1, data).unwrap();
+        ImageTexture::new(DynamicImage::ImageRgb32F(image))
+    }
+
+    fn gray(texture: &ImageTexture, u: f32) -> f32 {
+        texture.color(&TexCoord::new(u, 0.5, Point::zero())).x
+    }
+
+    #[test]
+    fn color_spaces_default_by_pixel_type() {
+        let float = DynamicImage::new_rgb32f(1, 1);
+        let eight_bit = DynamicImage::new_rgb8(1, 1);
+        let sixteen_bit = DynamicImage::new_rgba16(1, 1);
+
+        assert_eq!(ColorSpace::default_for(&float), ColorSpace::Linear);
+        assert_eq!(ColorSpace::default_for(&eight_bit), ColorSpace::Srgb);
+        assert_eq!(ColorSpace::default_for(&sixteen_bit), ColorSpace::Srgb);
+    }
+
+    #[test]
+    fn srgb_images_decode_to_linear_color_but_not_alpha() {
+        let image = || DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([128; 4])));
+        let coord = TexCoord::new(0.5, 0.5, Point::zero());
+
+        let srgb = ImageTexture::new(image());
+        assert!((srgb.color(&coord).x - 0.2158605).abs() < 1e-5);
+        assert!((srgb.alpha(0.5, 0.5) - 128.0 / 255.0).abs() < 1e-6);
+
+        let linear = ImageTexture::decode(image(), ColorSpace::Linear);
+        assert_eq!(linear.color_space(), ColorSpace::Linear);
+        assert!((linear.color(&coord).x - 128.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn high_precision_images_keep_their_precision_and_range() {
+        assert_eq!(gray(&row(&[12.5]), 0.5), 12.5);
+
+        let image = ImageBuffer::from_pixel(1, 1, Rgb([1000u16; 3]));
+        let texture = ImageTexture::decode(DynamicImage::ImageRgb16(image), ColorSpace::Linear);
+        // Eight bits would round the value to 4 / 255, 1.6e-2 rather than 1.5e-2.
+        assert!((gray(&texture, 0.5) - 1000.0 / 65535.0).abs() < 1e-7);
+    }
+
+    #[test]
+    fn loading_errors_tell_what_went_wrong() {
+        let missing = temp_path("missing.png");
+        assert!(matches!(
+            ImageTexture::open(&missing),
+            Err(Error::Io { .. })
+        ));
+
+        let text = temp_path("text.png");
+        fs::write(&text, "not an image").unwrap();
+        let unsupported = ImageTexture::open(&text);
+        fs::remove_file(&text).unwrap();
+        assert!(matches!(unsupported, Err(Error::UnsupportedFormat { .. })));
+
+        // A PNG signature followed by garbage is recognized, but cannot be decoded.
+        let corrupt = temp_path("corrupt.png");
+        fs::write(&corrupt, b"\x89PNG\r\n\x1a\n garbage").unwrap();
+        let undecodable = ImageTexture::open(&corrupt);
+        fs::remove_file(&corrupt).unwrap();
+        assert!(
+            matches!(undecodable, Err(Error::Decode { .. })),
+            "{:?}",
+            undecodable.err()
+        );
+    }
+
+    #[test]
+    fn pyramids_halve_down_to_a_single_texel() {
+        let sizes = |width, height| {
+            let image = DynamicImage::new_rgb32f(width, height);
+            build_pyramid(image, ColorSpace::Linear)
+                .iter()
+                .map(|level| (level.width, level.height))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sizes(8, 4), [(8, 4), (4, 2), (2, 1), (1, 1)]);
+        assert_eq!(sizes(5, 3), [(5, 3), (2, 1), (1, 1)]);
+        assert_eq!(sizes(1, 1), [(1, 1)]);
+    }
+
+    #[test]
+    fn wide_footprints_read_the_average_of_the_image() {
+        let texture = row(&[0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(texture.levels.last().unwrap().texels[0][0], 0.5);
+
+        let coord = |dudx| TexCoord {
+            dudx,
+            ..TexCoord::new(0.4, 0.5, Point::zero())
+        };
+        assert_eq!(texture.color(&coord(0.0)).x, 1.0);
+        assert!((texture.color(&coord(2.0)).x - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn wrap_modes_map_indices_into_the_image() {
+        let indices = [-5, -1, 0, 3, 4, 7, 9];
+
+        let clamped = indices.map(|i| Wrap::Clamp.apply(i, 4));
+        let repeated = indices.map(|i| Wrap::Repeat.apply(i, 4));
+        let mirrored = indices.map(|i| Wrap::Mirror.apply(i, 4));
+
+        assert_eq!(clamped, [0, 0, 0, 3, 3, 3, 3]);
+        assert_eq!(repeated, [3, 3, 0, 3, 0, 3, 1]);
+        assert_eq!(mirrored, [3, 0, 0, 3, 3, 0, 1]);
+    }
+
+    #[test]
+    fn wrap_modes_apply_outside_the_unit_square() {
+        let texture = row(&[0.0, 1.0]);
+
+        assert_eq!(gray(&texture.clone().with_wrap(Wrap::Clamp), 1.25), 1.0);
+        assert_eq!(gray(&texture.clone().with_wrap(Wrap::Repeat), 1.25), 0.0);
+        assert_eq!(gray(&texture.with_wrap(Wrap::Mirror), 1.25), 1.0);
+    }
+
+    #[test]
+    fn bilinear_filtering_blends_between_texel_centers() {
+        let nearest = row(&[0.0, 1.0]);
+        let bilinear = nearest.clone().with_filter(Filter::Bilinear);
+
+        // At texel centers both filters agree.
+        assert_eq!(gray(&nearest, 0.25), 0.0);
+        assert_eq!(gray(&bilinear, 0.25), 0.0);
+        assert_eq!(gray(&bilinear, 0.75), 1.0);
+
+        // Halfway between the centers, nearest snaps and bilinear blends.
+        assert_eq!(gray(&nearest, 0.5), 1.0);
+        assert!((gray(&bilinear, 0.5) - 0.5).abs() < 1e-6);
+        assert!((gray(&bilinear, 0.375) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bicubic_filtering_does_not_overshoot_below_zero() {
+        let texture = row(&[0.0, 0.0, 1.0, 1.0]).with_filter(Filter::Bicubic);
+
+        for i in 0..=16 {
+            assert!(gray(&texture, i as f32 / 16.0) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn uv_transforms_scale_then_rotate_then_offset() {
+        let transform = UvTransform {
+            offset: (0.5, 0.0),
+            scale: (2.0, 1.0),
+            rotation: 90.0,
+        };
+
+        let (u, v) = transform.apply(1.0, 0.0);
+        assert!(
+            (u - 0.5).abs() < 1e-6 && (v - 2.0).abs() < 1e-6,
+            "({u}, {v})"
+        );
+        let (du, dv) = transform.apply_vector(1.0, 0.0);
+        assert!(du.abs() < 1e-6 && (dv - 2.0).abs() < 1e-6, "({du}, {dv})");
+
+        let shifted = row(&[0.0, 1.0]).with_transform(UvTransform {
+            offset: (0.5, 0.0),
+            ..UvTransform::default()
+        });
+        assert_eq!(gray(&shifted, 0.25), 1.0);
+    }
+}
@@ -0,0 +1,656 @@
+mod cache;
+mod image_texture;
+mod ramp;
+
+use std::path::Path;
+
+use image::DynamicImage;
+
+use crate::{
+    error::Result,
+    hittable::HitRecord,
+    noise::{fractal::Fractal, perlin::Perlin, simplex::Simplex, worley::Worley},
+    vector::{Color, Point, Vector3},
+};
+
+pub use self::cache::TextureCache;
+pub use self::image_texture::{ColorSpace, Filter, ImageTexture, UvTransform, Wrap};
+pub use self::ramp::ColorRamp;
+
+/// Where a texture is looked up, along with the screen-space footprint of the
+/// lookup. A zero footprint point-samples the texture.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TexCoord {
+    pub u: f32,
+    pub v: f32,
+    pub p: Point,
+    pub local_p: Point,
+    pub normal: Vector3,
+    pub dpdx: Vector3,
+    pub dpdy: Vector3,
+    pub local_dpdx: Vector3,
+    pub local_dpdy: Vector3,
+    pub dudx: f32,
+    pub dudy: f32,
+    pub dvdx: f32,
+    pub dvdy: f32,
+}
+
+impl TexCoord {
+    pub fn new(u: f32, v: f32, p: Point) -> Self {
+        Self {
+            u,
+            v,
+            p,
+            local_p: p,
+            ..Default::default()
+        }
+    }
+}
+
+impl TexCoord {
+    /// Returns the coordinates with `p` (and its derivatives) taken from `space`.
+    pub fn in_space(&self, space: TextureSpace) -> Self {
+        match space {
+            TextureSpace::World => *self,
+            TextureSpace::Object => Self {
+                p: self.local_p,
+                dpdx: self.local_dpdx,
+                dpdy: self.local_dpdy,
+                ..*self
+            },
+            TextureSpace::Uv => Self {
+                p: Point::new(self.u, self.v, 0.0),
+                dpdx: Vector3::new(self.dudx, self.dvdx, 0.0),
+                dpdy: Vector3::new(self.dudy, self.dvdy, 0.0),
+                ..*self
+            },
+        }
+    }
+}
+
+impl From<&HitRecord<'_>> for TexCoord {
+    fn from(rec: &HitRecord) -> Self {
+        Self {
+            u: rec.u,
+            v: rec.v,
+            p: rec.p,
+            local_p: rec.local_p,
+            normal: rec.normal,
+            dpdx: rec.dpdx,
+            dpdy: rec.dpdy,
+            local_dpdx: rec.local_dpdu * rec.dudx + rec.local_dpdv * rec.dvdx,
+            local_dpdy: rec.local_dpdu * rec.dudy + rec.local_dpdv * rec.dvdy,
+            dudx: rec.dudx,
+            dudy: rec.dudy,
+            dvdx: rec.dvdx,
+            dvdy: rec.dvdy,
+        }
+    }
+}
+
+/// The coordinates a texture sees as its `point`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureSpace {
+    /// The world-space hit point.
+    #[default]
+    World,
+    /// The hit point in the primitive's own frame, so the texture sticks to the
+    /// object when it is moved, rotated or in motion.
+    Object,
+    /// The surface coordinates, as the point `(u, v, 0)`.
+    Uv,
+}
+
+#[derive(Clone)]
+pub enum Texture {
+    SolidColor(Color),
+    Checkered {
+        even: Color,
+        odd: Color,
+        scale: f32,
+    },
+    Image(ImageTexture),
+    Perlin(Perlin),
+    Turbulence(Perlin),
+    Simplex(Simplex),
+    Worley(Worley),
+    Fractal(Fractal),
+    /// Veined marble: a sine wave along z, distorted by `turbulence` times the
+    /// turbulence of `noise`. `scale` sets the frequency of the veins.
+    Marble {
+        noise: Perlin,
+        scale: f32,
+        turbulence: f32,
+    },
+    /// Concentric rings around the y axis, `frequency` rings per unit, with the
+    /// ring radius jittered by `distortion` times `noise`.
+    Wood {
+        noise: Perlin,
+        frequency: f32,
+        distortion: f32,
+    },
+    /// Speckled granite built from high-frequency folded noise; `scale` sets the
+    /// size of the grains.
+    Granite {
+        noise: Perlin,
+        scale: f32,
+    },
+    /// Maps the scalar value of `input` to a color through `ramp`.
+    Ramp {
+        input: Box<Texture>,
+        ramp: ColorRamp,
+    },
+    /// Channel-wise product of two textures.
+    Multiply(Box<Texture>, Box<Texture>),
+    /// Channel-wise sum of two textures.
+    Add(Box<Texture>, Box<Texture>),
+    /// Blends `first` into `second` by the scalar value of `factor`.
+    Mix {
+        first: Box<Texture>,
+        second: Box<Texture>,
+        factor: Box<Texture>,
+    },
+    /// Replaces the color of `input` with its scalar value on every channel.
+    Grayscale(Box<Texture>),
+    /// Looks `texture` up with its `(u, v)` remapped by `transform`.
+    TransformUv {
+        texture: Box<Texture>,
+        transform: UvTransform,
+    },
+    /// Looks `texture` up at `point * scale + offset`.
+    TransformPoint {
+        texture: Box<Texture>,
+        scale: Vector3,
+        offset: Vector3,
+    },
+    /// Projects `texture` along the three axes, using the world position scaled by
+    /// `scale` as `(u, v)`, and blends the projections by the normal raised to
+    /// `sharpness`.
+    Triplanar {
+        texture: Box<Texture>,
+        scale: f32,
+        sharpness: f32,
+    },
+    /// Evaluates `texture` with its `point` taken from `space`, which turns any 3D
+    /// procedural (e.g. `Checkered`) into an object-space or UV-space one.
+    Space {
+        texture: Box<Texture>,
+        space: TextureSpace,
+    },
+    /// 3D checker alternating between two textures.
+    CheckerOf {
+        even: Box<Texture>,
+        odd: Box<Texture>,
+        scale: f32,
+    },
+}
+impl Texture {
+    pub fn load_image<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::Image(ImageTexture::open(path)?))
+    }
+
+    /// Wraps the texture so it is evaluated in `space`.
+    pub fn in_space(self, space: TextureSpace) -> Self {
+        Texture::Space {
+            texture: Box::new(self),
+            space,
+        }
+    }
+
+    /// Evaluates the texture as a single value, averaging the color channels.
+    pub fn scalar(&self, u: f32, v: f32, point: &Point) -> f32 {
+        self.scalar_value(&TexCoord::new(u, v, *point))
+    }
+
+    /// Filtered counterpart of `scalar`.
+    pub fn scalar_value(&self, coord: &TexCoord) -> f32 {
+        let color = self.value(coord);
+        (color.x + color.y + color.z) / 3.0
+    }
+
+    /// Evaluates the texture as an opacity mask. Image textures read their alpha
+    /// channel, every other texture uses its scalar value.
+    pub fn alpha(&self, u: f32, v: f32, point: &Point) -> f32 {
+        self.alpha_value(&TexCoord::new(u, v, *point))
+    }
+
+    /// Filtered counterpart of `alpha`.
+    pub fn alpha_value(&self, coord: &TexCoord) -> f32 {
+        match self {
+            Texture::Image(image) => image.alpha(coord.u, coord.v),
+            _ => self.scalar_value(coord),
+        }
+    }
+
+    pub fn color(&self, u: f32, v: f32, point: &Point) -> Color {
+        self.value(&TexCoord::new(u, v, *point))
+    }
+
+    /// Evaluates the texture, filtering it over the footprint carried by `coord`.
+    pub fn value(&self, coord: &TexCoord) -> Color {
+        let point = &coord.p;
+
+        match self {
+            Texture::SolidColor(color) => *color,
+            Texture::Checkered { even, odd, scale } => {
+                let sign = checker_sign(coord, *scale);
+
+                *even * (0.5 * (1.0 + sign)) + *odd * (0.5 * (1.0 - sign))
+            }
+            Texture::Image(image) => image.color(coord),
+            Texture::Perlin(perlin) => {
+                // let noise = perlin.get(((point * 5.) + 1.0 / 2.0).to_array());
+
+                let noise = perlin.improved_noise(point);
+
+                Color::white() * noise
+            }
+            Texture::Turbulence(perlin) => {
+                let noise = perlin.turbulence(point, 7);
+
+                Color::white() * noise
+            }
+            Texture::Simplex(simplex) => Color::white() * simplex.noise(point),
+            Texture::Worley(worley) => Color::white() * worley.noise(point),
+            Texture::Fractal(fractal) => Color::white() * fractal.noise(point),
+            Texture::Marble {
+                noise,
+                scale,
+                turbulence,
+            } => {
+                let phase = scale * point.z + turbulence * noise.turbulence(point, 7);
+
+                Color::white() * 0.5 * (1.0 + phase.sin())
+            }
+            Texture::Wood {
+                noise,
+                frequency,
+                distortion,
+            } => {
+                let radius = (point.x * point.x + point.z * point.z).sqrt();
+                let rings = radius * frequency + distortion * noise.improved_noise(point);
+
+                Color::white() * (rings - rings.floor())
+            }
+            Texture::Granite { noise, scale } => {
+                let mut accum = 0.0;
+                let mut frequency = 1.0;
+
+                (0..6).for_each(|_| {
+                    let n = noise.improved_noise(&(*point * (scale * frequency)));
+                    accum += (0.5 - n.abs()).abs() / frequency;
+                    frequency *= 2.0;
+                });
+
+                Color::white() * accum.clamp(0.0, 1.0)
+            }
+            Texture::Ramp { input, ramp } => ramp.color(input.scalar_value(coord)),
+            Texture::Multiply(a, b) => a.value(coord) * b.value(coord),
+            Texture::Add(a, b) => a.value(coord) + b.value(coord),
+            Texture::Mix {
+                first,
+                second,
+                factor,
+            } => {
+                let t = factor.scalar_value(coord).clamp(0.0, 1.0);
+                first.value(coord) * (1.0 - t) + second.value(coord) * t
+            }
+            Texture::Grayscale(input) => Color::from_one(input.scalar_value(coord)),
+            Texture::TransformUv { texture, transform } => {
+                let (u, v) = transform.apply(coord.u, coord.v);
+                let (dudx, dvdx) = transform.apply_vector(coord.dudx, coord.dvdx);
+                let (dudy, dvdy) = transform.apply_vector(coord.dudy, coord.dvdy);
+
+                texture.value(&TexCoord {
+                    u,
+                    v,
+                    dudx,
+                    dudy,
+                    dvdx,
+                    dvdy,
+                    ..*coord
+                })
+            }
+            Texture::TransformPoint {
+                texture,
+                scale,
+                offset,
+            } => texture.value(&TexCoord {
+                p: *point * *scale + offset,
+                dpdx: coord.dpdx * *scale,
+                dpdy: coord.dpdy * *scale,
+                ..*coord
+            }),
+            Texture::Triplanar {
+                texture,
+                scale,
+                sharpness,
+            } => {
+                let weights = Vector3::new(
+                    coord.normal.x.abs().powf(*sharpness),
+                    coord.normal.y.abs().powf(*sharpness),
+                    coord.normal.z.abs().powf(*sharpness),
+                );
+                let total = weights.x + weights.y + weights.z;
+                if total <= 0.0 {
+                    return Color::black();
+                }
+
+                // Project along x onto (z, y), along y onto (x, z) and along z onto (x, y).
+                let project = |u: fn(&Vector3) -> f32, v: fn(&Vector3) -> f32| {
+                    texture.value(&TexCoord {
+                        u: u(point) * scale,
+                        v: v(point) * scale,
+                        dudx: u(&coord.dpdx) * scale,
+                        dudy: u(&coord.dpdy) * scale,
+                        dvdx: v(&coord.dpdx) * scale,
+                        dvdy: v(&coord.dpdy) * scale,
+                        ..*coord
+                    })
+                };
+
+                (project(|p| p.z, |p| p.y) * weights.x
+                    + project(|p| p.x, |p| p.z) * weights.y
+                    + project(|p| p.x, |p| p.y) * weights.z)
+                    / total
+            }
+            Texture::Space { texture, space } => texture.value(&coord.in_space(*space)),
+            Texture::CheckerOf { even, odd, scale } => {
+                let t = 0.5 * (1.0 + checker_sign(coord, *scale));
+
+                // Only evaluate both sides where the footprint straddles an edge.
+                if t >= 1.0 {
+                    even.value(coord)
+                } else if t <= 0.0 {
+                    odd.value(coord)
+                } else {
+                    even.value(coord) * t + odd.value(coord) * (1.0 - t)
+                }
+            }
+        }
+    }
+}
+
+/// Sign of a 3D checker with cells of size `scale` at `coord.p`: `1.0` on even
+/// cells, `-1.0` on odd ones, and in between where the footprint covers both.
+fn checker_sign(coord: &TexCoord, scale: f32) -> f32 {
+    let inv_scale = scale.recip();
+    let point = &coord.p;
+
+    // Box-filter the checker over the footprint, one axis at a time. The checker
+    // sign is the product of three square waves, and each one averages
+    // independently over its axis.
+    let width = |dx: f32, dy: f32| dx.abs().max(dy.abs()) * inv_scale;
+
+    square_wave(point.x * inv_scale, width(coord.dpdx.x, coord.dpdy.x))
+        * square_wave(point.y * inv_scale, width(coord.dpdx.y, coord.dpdy.y))
+        * square_wave(point.z * inv_scale, width(coord.dpdx.z, coord.dpdy.z))
+}
+
+/// Average of the square wave `(-1)^floor(x)` over `[x - width / 2, x + width / 2]`.
+/// A zero `width` point-samples it.
+fn square_wave(x: f32, width: f32) -> f32 {
+    if width <= 1e-6 {
+        return if x.floor().rem_euclid(2.0) == 0.0 {
+            1.0
+        } else {
+            -1.0
+        };
+    }
+    // The integral of the square wave is a triangle wave.
+    let integral = |x: f32| {
+        let frac = x - x.floor();
+        if x.floor().rem_euclid(2.0) == 0.0 {
+            frac
+        } else {
+            1.0 - frac
+        }
+    };
+
+    (integral(x + 0.5 * width) - integral(x - 0.5 * width)) / width
+}
+
+impl From<DynamicImage> for Texture {
+    fn from(value: DynamicImage) -> Self {
+        Self::Image(ImageTexture::new(value))
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(value: Color) -> Self {
+        Self::SolidColor(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{square_wave, ColorRamp, TexCoord, Texture, TextureSpace, UvTransform};
+    use crate::{
+        noise::perlin::Perlin,
+        vector::{Color, Point, Vector3},
+    };
+
+    fn solid(r: f32, g: f32, b: f32) -> Box<Texture> {
+        Box::new(Texture::SolidColor(Color::new(r, g, b)))
+    }
+
+    /// A black and white checker of unit cells, black on odd cells.
+    fn checker() -> Texture {
+        Texture::Checkered {
+            even: Color::white(),
+            odd: Color::black(),
+            scale: 1.0,
+        }
+    }
+
+    fn rgb(color: Color) -> [f32; 3] {
+        [color.x, color.y, color.z]
+    }
+
+    #[test]
+    fn arithmetic_nodes_combine_channels() {
+        let coord = TexCoord::new(0.0, 0.0, Point::zero());
+        let value = |texture: Texture| rgb(texture.value(&coord));
+
+        assert_eq!(
+            value(Texture::Multiply(
+                solid(0.5, 1.0, 2.0),
+                solid(0.5, 0.25, 0.5)
+            )),
+            [0.25, 0.25, 1.0]
+        );
+        assert_eq!(
+            value(Texture::Add(solid(0.5, 1.0, 2.0), solid(0.5, 0.25, 0.5))),
+            [1.0, 1.25, 2.5]
+        );
+        assert_eq!(
+            value(Texture::Mix {
+                first: solid(1.0, 0.0, 0.0),
+                second: solid(0.0, 0.0, 1.0),
+                factor: solid(0.25, 0.25, 0.25),
+            }),
+            [0.75, 0.0, 0.25]
+        );
+        assert_eq!(value(Texture::Grayscale(solid(0.0, 0.75, 1.5))), [0.75; 3]);
+    }
+
+    #[test]
+    fn transform_nodes_remap_their_lookups() {
+        let coord = TexCoord::new(0.5, 0.5, Point::new(0.5, 0.5, 0.5));
+        let value = |texture: Texture| texture.value(&coord).x;
+        assert_eq!(value(checker()), 1.0);
+        assert_eq!(value(checker().in_space(TextureSpace::Uv)), 1.0);
+
+        let shifted_uv = Texture::TransformUv {
+            texture: Box::new(checker().in_space(TextureSpace::Uv)),
+            transform: UvTransform {
+                offset: (1.0, 0.0),
+                ..UvTransform::default()
+            },
+        };
+        assert_eq!(value(shifted_uv), 0.0);
+
+        let shifted_point = Texture::TransformPoint {
+            texture: Box::new(checker()),
+            scale: Vector3::from_one(1.0),
+            offset: Vector3::new(0.0, 0.0, 1.0),
+        };
+        assert_eq!(value(shifted_point), 0.0);
+
+        let scaled_point = Texture::TransformPoint {
+            texture: Box::new(checker()),
+            scale: Vector3::new(3.0, 1.0, 1.0),
+            offset: Vector3::zero(),
+        };
+        assert_eq!(value(scaled_point), 0.0);
+    }
+
+    #[test]
+    fn space_nodes_pick_the_point_of_their_space() {
+        let coord = TexCoord {
+            local_p: Point::new(1.5, 0.5, 0.5),
+            ..TexCoord::new(0.5, 1.5, Point::new(0.5, 0.5, 0.5))
+        };
+        let value = |space| checker().in_space(space).value(&coord).x;
+
+        assert_eq!(value(TextureSpace::World), 1.0);
+        assert_eq!(value(TextureSpace::Object), 0.0);
+        assert_eq!(value(TextureSpace::Uv), 0.0);
+    }
+
+    #[test]
+    fn triplanar_projections_follow_the_normal() {
+        let texture = Texture::Triplanar {
+            texture: Box::new(checker().in_space(TextureSpace::Uv)),
+            scale: 1.0,
+            sharpness: 4.0,
+        };
+        // The point is on an odd cell seen along z, and an even one along x.
+        let point = Point::new(0.5, 1.5, 1.5);
+        let facing = |normal| TexCoord {
+            normal,
+            ..TexCoord::new(0.0, 0.0, point)
+        };
+
+        let along_z = texture.value(&facing(Vector3::new(0.0, 0.0, -1.0)));
+        assert_eq!(along_z.x, 0.0);
+        let along_x = texture.value(&facing(Vector3::new(1.0, 0.0, 0.0)));
+        assert_eq!(along_x.x, 1.0);
+        let diagonal = texture.value(&facing(Vector3::new(1.0, 0.0, 1.0).unit()));
+        assert!((diagonal.x - 0.5).abs() < 1e-5, "{diagonal:?}");
+    }
+
+    #[test]
+    fn checkers_of_textures_alternate_between_them() {
+        let texture = Texture::CheckerOf {
+            even: solid(1.0, 0.0, 0.0),
+            odd: Box::new(Texture::Grayscale(solid(0.0, 0.0, 0.75))),
+            scale: 2.0,
+        };
+        let value = |x| rgb(texture.value(&TexCoord::new(0.0, 0.0, Point::new(x, 0.5, 0.5))));
+
+        assert_eq!(value(1.0), [1.0, 0.0, 0.0]);
+        assert_eq!(value(3.0), [0.25; 3]);
+        assert_eq!(value(-1.0), [0.25; 3]);
+    }
+
+    fn points() -> impl Iterator<Item = Point> {
+        (0..300).map(|i| {
+            let i = i as f32;
+            Point::new(i * 0.37 - 40.0, i * 0.173 + 0.5, -i * 0.091 + 2.25)
+        })
+    }
+
+    #[test]
+    fn procedural_materials_are_gray_and_in_range() {
+        let noise = Perlin::new(4);
+        let textures = [
+            Texture::Marble {
+                noise: noise.clone(),
+                scale: 4.0,
+                turbulence: 10.0,
+            },
+            Texture::Wood {
+                noise: noise.clone(),
+                frequency: 6.0,
+                distortion: 0.5,
+            },
+            Texture::Granite { noise, scale: 20.0 },
+        ];
+
+        for texture in &textures {
+            for p in points() {
+                let color = texture.color(0.0, 0.0, &p);
+                assert!((0.0..=1.0).contains(&color.x), "{color:?} at {p:?}");
+                assert!(color.x == color.y && color.y == color.z, "{color:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn undistorted_wood_rings_follow_the_radius() {
+        let wood = Texture::Wood {
+            noise: Perlin::default(),
+            frequency: 2.0,
+            distortion: 0.0,
+        };
+
+        // A quarter unit from the axis is half a ring in, at any height.
+        for y in [-1.0, 0.0, 3.5] {
+            let color = wood.color(0.0, 0.0, &Point::new(0.0, y, 0.25));
+            assert!((color.x - 0.5).abs() < 1e-6, "{color:?}");
+        }
+    }
+
+    #[test]
+    fn ramps_color_the_scalar_of_their_input() {
+        let texture = Texture::Ramp {
+            input: Box::new(Texture::SolidColor(Color::new(0.0, 0.5, 1.0))),
+            ramp: ColorRamp::new(vec![
+                (0.0, Color::black()),
+                (1.0, Color::new(2.0, 0.0, 0.0)),
+            ]),
+        };
+
+        let color = texture.color(0.0, 0.0, &Point::zero());
+        assert!((color.x - 1.0).abs() < 1e-6 && color.y == 0.0, "{color:?}");
+    }
+
+    #[test]
+    fn square_waves_average_over_their_width() {
+        assert_eq!(square_wave(0.5, 0.0), 1.0);
+        assert_eq!(square_wave(1.5, 0.0), -1.0);
+        assert_eq!(square_wave(-0.5, 0.0), -1.0);
+
+        // Within a single cell the average is the point value; centered on an
+        // edge, or over a whole period, the cells cancel out.
+        assert!((square_wave(0.5, 0.5) - 1.0).abs() < 1e-6);
+        assert!(square_wave(1.0, 0.5).abs() < 1e-6);
+        assert!(square_wave(0.3, 2.0).abs() < 1e-6);
+        assert!((square_wave(0.75, 1.0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn checkers_fade_to_their_average_under_wide_footprints() {
+        let checker = Texture::Checkered {
+            even: Color::white(),
+            odd: Color::black(),
+            scale: 1.0,
+        };
+        let point = Point::new(0.25, 0.5, 0.75);
+
+        let sharp = checker.value(&TexCoord::new(0.0, 0.0, point));
+        assert!(sharp.x == 0.0 || sharp.x == 1.0, "{sharp:?}");
+
+        let blurred = checker.value(&TexCoord {
+            dpdx: Vector3::new(20.0, 0.0, 0.0),
+            dpdy: Vector3::new(0.0, 20.0, 20.0),
+            ..TexCoord::new(0.0, 0.0, point)
+        });
+        assert!((blurred.x - 0.5).abs() < 0.05, "{blurred:?}");
+    }
+}
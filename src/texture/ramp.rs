@@ -0,0 +1,84 @@
+use crate::{lerp, vector::Color};
+
+/// Piecewise-linear gradient mapping a scalar to a color. Values outside the
+/// range of the stops take the color of the nearest end stop.
+#[derive(Clone, Debug)]
+pub struct ColorRamp {
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp {
+    /// Builds a ramp from `(position, color)` stops, in any order.
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    pub fn color(&self, t: f32) -> Color {
+        let Some(&(first_t, first_color)) = self.stops.first() else {
+            return Color::black();
+        };
+        if t <= first_t {
+            return first_color;
+        }
+
+        self.stops
+            .windows(2)
+            .find(|pair| t <= pair[1].0)
+            .map(|pair| {
+                let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+                let s = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                Color::new(
+                    lerp(s, c0.x, c1.x),
+                    lerp(s, c0.y, c1.y),
+                    lerp(s, c0.z, c1.z),
+                )
+            })
+            .unwrap_or_else(|| self.stops[self.stops.len() - 1].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorRamp;
+    use crate::vector::Color;
+
+    fn rgb(color: Color) -> [f32; 3] {
+        [color.x, color.y, color.z]
+    }
+
+    #[test]
+    fn ramps_interpolate_between_sorted_stops() {
+        let ramp = ColorRamp::new(vec![
+            (1.0, Color::new(0.0, 0.0, 1.0)),
+            (0.0, Color::new(1.0, 0.0, 0.0)),
+            (0.5, Color::new(0.0, 1.0, 0.0)),
+        ]);
+
+        assert_eq!(rgb(ramp.color(0.0)), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb(ramp.color(0.25)), [0.5, 0.5, 0.0]);
+        assert_eq!(rgb(ramp.color(0.5)), [0.0, 1.0, 0.0]);
+        assert_eq!(rgb(ramp.color(0.75)), [0.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn ramps_hold_their_end_colors() {
+        let ramp = ColorRamp::new(vec![
+            (0.2, Color::from_one(0.1)),
+            (0.8, Color::from_one(0.9)),
+        ]);
+
+        assert_eq!(rgb(ramp.color(-3.0)), [0.1; 3]);
+        assert_eq!(rgb(ramp.color(5.0)), [0.9; 3]);
+        assert_eq!(rgb(ramp.color(f32::NAN)), [0.9; 3]);
+    }
+
+    #[test]
+    fn coincident_stops_make_a_hard_edge() {
+        let ramp = ColorRamp::new(vec![(0.5, Color::black()), (0.5, Color::white())]);
+
+        assert_eq!(rgb(ramp.color(0.4)), [0.0; 3]);
+        assert_eq!(rgb(ramp.color(0.6)), [1.0; 3]);
+        assert_eq!(rgb(ColorRamp::new(Vec::new()).color(0.5)), [0.0; 3]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+
+/// A rectangle of pixels, from `(x0, y0)` up to but not including `(x1, y1)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Rect {
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Self {
+        Self {
+            x0,
+            y0,
+            x1: x1.max(x0),
+            y1: y1.max(y0),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width() as u64 * self.height() as u64
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x0..self.x1).contains(&x) && (self.y0..self.y1).contains(&y)
+    }
+
+    /// The part of the rectangle inside `other`, which may be empty.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        Rect::new(
+            self.x0.max(other.x0),
+            self.y0.max(other.y0),
+            self.x1.min(other.x1),
+            self.y1.min(other.y1),
+        )
+    }
+
+    /// The pixels of the rectangle, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let Rect { x0, y0, x1, y1 } = *self;
+        (y0..y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} pixels at ({}, {})",
+            self.width(),
+            self.height(),
+            self.x0,
+            self.y0
+        )
+    }
+}
+
+/// The order tiles are handed out to the render threads in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    #[default]
+    Scanline,
+    /// Outwards from the center, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other.
+    Hilbert,
+}
+
+/// Splits `bounds` into tiles of at most `size` by `size` pixels, in `order`.
+pub fn tiles(bounds: Rect, size: u32, order: TileOrder) -> Vec<Rect> {
+    let size = size.max(1);
+    let columns = bounds.width().div_ceil(size);
+    let rows = bounds.height().div_ceil(size);
+
+    let grid: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => {
+            let side = columns.max(rows).next_power_of_two();
+            let mut grid: Vec<(u32, u32)> = (0..rows)
+                .flat_map(|row| (0..columns).map(move |column| (column, row)))
+                .collect();
+            grid.sort_by_key(|&(column, row)| hilbert_index(side, column, row));
+            grid
+        }
+    };
+
+    grid.into_iter()
+        .map(|(column, row)| {
+            let x0 = bounds.x0 + column * size;
+            let y0 = bounds.y0 + row * size;
+            Rect::new(x0, y0, x0 + size, y0 + size).intersect(&bounds)
+        })
+        .collect()
+}
+
+/// Walks a square spiral out from the center cell, keeping the cells that fall
+/// inside a `columns` by `rows` grid.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let count = (columns * rows) as usize;
+    let mut cells = Vec::with_capacity(count);
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 1;
+    let mut direction = 0;
+
+    while cells.len() < count {
+        // Each leg length is walked twice before it grows: right 1, down 1,
+        // left 2, up 2, right 3, and so on.
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction % 4];
+            for _ in 0..leg {
+                if (0..columns as i64).contains(&x) && (0..rows as i64).contains(&y) {
+                    cells.push((x as u32, y as u32));
+                }
+                x += dx;
+                y += dy;
+            }
+            direction += 1;
+        }
+        leg += 1;
+    }
+
+    cells
+}
+
+/// Distance along the Hilbert curve filling a `side` by `side` grid, where
+/// `side` is a power of two.
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = side / 2;
+
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        // Rotate the quadrant so the curve inside it lines up with its neighbours.
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tiles, Rect, TileOrder};
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    #[test]
+    fn tiles_cover_the_bounds_exactly_once() {
+        let bounds = [
+            Rect::new(0, 0, 64, 64),
+            Rect::new(0, 0, 100, 37),
+            Rect::new(13, 7, 50, 90),
+            Rect::new(5, 5, 6, 6),
+        ];
+
+        for bounds in bounds {
+            for order in ORDERS {
+                for size in [1, 7, 16, 200] {
+                    let mut covered = tiles(bounds, size, order)
+                        .iter()
+                        .inspect(|tile| {
+                            assert!(tile.width() <= size && tile.height() <= size);
+                            assert_eq!(tile.intersect(&bounds), **tile);
+                        })
+                        .flat_map(Rect::pixels)
+                        .collect::<Vec<_>>();
+                    covered.sort_unstable();
+
+                    let mut expected = bounds.pixels().collect::<Vec<_>>();
+                    expected.sort_unstable();
+                    assert_eq!(covered, expected, "{order:?} tiles of {size} over {bounds}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_bounds_have_no_tiles() {
+        for order in ORDERS {
+            assert!(tiles(Rect::new(4, 4, 4, 10), 8, order).is_empty());
+        }
+    }
+
+    #[test]
+    fn scanline_tiles_go_row_by_row() {
+        let origins = tiles(Rect::new(0, 0, 20, 20), 8, TileOrder::Scanline)
+            .iter()
+            .map(|tile| (tile.x0, tile.y0))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            origins,
+            [
+                (0, 0),
+                (8, 0),
+                (16, 0),
+                (0, 8),
+                (8, 8),
+                (16, 8),
+                (0, 16),
+                (8, 16),
+                (16, 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn spirals_start_in_the_center() {
+        let spiral = tiles(Rect::new(0, 0, 50, 50), 10, TileOrder::Spiral);
+
+        assert_eq!(spiral[0], Rect::new(20, 20, 30, 30));
+        assert_eq!(spiral[1], Rect::new(30, 20, 40, 30));
+        assert_eq!(spiral[2], Rect::new(30, 30, 40, 40));
+    }
+
+    #[test]
+    fn hilbert_tiles_follow_each_other() {
+        let hilbert = tiles(Rect::new(0, 0, 64, 64), 8, TileOrder::Hilbert);
+
+        assert_eq!(hilbert.len(), 64);
+        for pair in hilbert.windows(2) {
+            let step = pair[0].x0.abs_diff(pair[1].x0) + pair[0].y0.abs_diff(pair[1].y0);
+            assert_eq!(step, 8, "{} then {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn rectangles_clip_to_each_other() {
+        let a = Rect::new(0, 0, 10, 10);
+
+        assert_eq!(a.intersect(&Rect::new(5, 2, 20, 8)), Rect::new(5, 2, 10, 8));
+        assert_eq!(a.intersect(&Rect::new(12, 12, 20, 20)).area(), 0);
+        assert_eq!(Rect::new(5, 5, 2, 2), Rect::new(5, 5, 5, 5));
+        assert!(a.contains(9, 0) && !a.contains(10, 0));
+    }
+}
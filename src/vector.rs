@@ -1,4 +1,5 @@
 use std::{
+    f32::consts::{FRAC_PI_2, FRAC_PI_4, PI},
     iter::Sum,
     ops::{Add, AddAssign, Div, Mul, Neg, Sub},
 };
@@ -104,6 +105,32 @@ impl Vector3 {
         }
     }
 
+    /// Maps a 2D sample in `[0, 1)^2` uniformly onto the unit sphere.
+    pub fn sample_unit_sphere((u1, u2): (f32, f32)) -> Vector3 {
+        let z = 1.0 - 2.0 * u1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        Vector3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Maps a 2D sample in `[0, 1)^2` uniformly onto the unit disk, with the
+    /// concentric mapping so strata stay compact.
+    pub fn sample_unit_disk((u1, u2): (f32, f32)) -> Vector3 {
+        let (x, y) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+        if x == 0.0 && y == 0.0 {
+            return Vector3::zero();
+        }
+
+        let (r, theta) = if x.abs() > y.abs() {
+            (x, FRAC_PI_4 * (y / x))
+        } else {
+            (y, FRAC_PI_2 - FRAC_PI_4 * (x / y))
+        };
+
+        Vector3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
     pub fn near_zero(&self) -> bool {
         let s = 1.0 * 10.0f32.powf(-8.0); // 1e-8
         (self.x < s) && (self.y < s) && (self.z < s)
@@ -311,6 +338,11 @@ impl Color {
         linear_component.powf(1.0 / GAMMA) // == sqrt(linear_component)
     }
 
+    /// Relative luminance, with the Rec. 709 weights.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
     pub fn white() -> Color {
         Color::from_one(1.0)
     }